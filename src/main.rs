@@ -10,8 +10,24 @@
 //! - **Visualize symbolic links** with `->` indicator showing both origin and destination
 //! - Show hidden files with the `-a` or `--all` flag
 //! - Limit traversal depth with the `-d` or `--depth` option
+//! - **Glob filtering** with `-P` (include) and `-I` (exclude); excluded directories are never read
+//! - **Regex filtering** with `--match-regex` and `--exclude-regex` on names or relative paths
+//! - **Filter by entry type** with `--type` (`d`, `f`, `l`, `s`, `p`, `b`, `c`)
+//! - **Filter by size** with `--min-size` and `--max-size` (`10M`, `1.5GiB`, ...)
+//! - **Filter by time** with `--newer-than` and `--older-than` on the mtime, ctime or atime
+//! - **Filter by owner and permissions** with `--user`, `--group` and `--perm`, like `find`
+//! - **Filter by content** with `--contains`, like grep but as a tree, with optional match counts
+//! - **Limit entries per directory** with `--filelimit`, eliding the rest with a summary line
+//! - **Prune empty directories** left over by the filters with `--prune`
+//! - **Honour ignore files** with `--gitignore` (`.gitignore`, `.ignore`, `.rutreeignore` and git's global excludes)
 //! - Sort entries alphabetically
+//! - **Show sizes** with `-s` (bytes), `-h` (powers of 1024) or `--si` (powers of 1000)
+//! - **Long listing** with `-l` (`ls -l`-style mode, owner, group and mtime) and `--columns`
+//! - **Disk usage per directory** with `--du`, from apparent sizes or allocated blocks
 //! - **Interactive collapsible/expandable tree mode** with `-i` or `--interactive` flag
+//! - **Selectable line-drawing charsets** with `--charset` (`utf8`, `ascii`, `heavy`, `rounded` or custom glyphs)
+//! - **Render path lists** from `git ls-files`, `tar tf` or `find` with `--fromfile`, without touching the filesystem
+//! - **Structured output** with `-o` or `--output` (`json`, `ndjson`, `xml`, `html`, `markdown`, `dot`, `mermaid`, `plantuml`, `csv`, `tsv`) for scripts and tools like `jq`
 //! - **Enhanced color-coded output** based on file types and permissions:
 //!   - **Permissions-based coloring:**
 //!     - Setuid files: White on red background (security sensitive)
@@ -26,6 +42,8 @@
 //!     - Image files (png, jpg, svg, etc.): Magenta
 //!     - Audio/video files (mp3, mp4, mkv, etc.): Bright magenta
 //!     - Special files (devices, sockets, pipes): Yellow (bold)
+//! - **Summary footer** with the number of directories, files and symlinks and their total size (`--noreport` hides it)
+//! - **Extension and category statistics** with `--stats`, as text, JSON, CSV or TSV
 //! - Clean, readable output with visual tree structure
 //!
 //! ## Usage
@@ -43,23 +61,108 @@
 //! # Limit depth to 2 levels
 //! rutree2 --depth 2
 //!
+//! # Only Rust sources, skipping build output entirely
+//! rutree2 -P '*.rs' -I target -I node_modules
+//!
+//! # Versioned migrations only, matched against the relative path
+//! rutree2 --match-regex '^db/migrations/V\d+__\w+\.sql$' --regex-on path
+//!
+//! # Only the Java sources, without the empty package folders around them
+//! rutree2 -P '*.java' --prune
+//!
+//! # Directory skeleton only, or the sockets under /run
+//! rutree2 --type d
+//! rutree2 --type s --prune /run
+//!
+//! # Oversized assets and the directories leading to them
+//! rutree2 --min-size 10M --prune
+//!
+//! # What changed in the build output during the last 3 days, or since the last deploy
+//! rutree2 --newer-than 3d --prune target
+//! rutree2 --newer-than /srv/app/DEPLOYED --prune /srv/app
+//!
+//! # Security audit: setuid, setgid and world-writable files owned by root
+//! rutree2 --perm setuid,setgid,world-writable --user root --prune /usr
+//!
+//! # Grep, but as a tree: files mentioning a function, with their matching line counts
+//! rutree2 --contains 'fn parse_\w+' --count --prune
+//!
+//! # Show at most 20 entries of each directory of an artifact cache
+//! rutree2 --filelimit 20 ~/.cache/artifacts
+//!
+//! # Hide whatever git ignores (build artifacts, node_modules, ...)
+//! rutree2 --gitignore
+//!
+//! # Show human-readable sizes next to the names
+//! rutree2 -h
+//!
+//! # ls -l style metadata, or just the columns you need
+//! rutree2 -l
+//! rutree2 --columns mode,inode,links
+//!
+//! # Where does the space go? Directory totals from allocated blocks
+//! rutree2 -h --du=allocated -d 2
+//!
+//! # File counts and sizes per extension and category, e.g. to track asset bloat
+//! rutree2 --stats
+//! rutree2 --stats -o csv > stats-$(date +%F).csv
+//!
 //! # Control color output (auto, always, never)
 //! rutree2 --color always
 //!
+//! # Plain ASCII tree for legacy terminals and log systems
+//! rutree2 --charset ascii
+//!
+//! # Render a list of paths read from stdin (or a file) as a tree
+//! git ls-files | rutree2 --fromfile
+//! find . -print0 | rutree2 --fromfile --null
+//!
 //! # Interactive collapsible/expandable tree view
 //! rutree2 --interactive
 //!
+//! # Nested JSON output
+//! rutree2 --output json
+//!
+//! # XML output compatible with GNU tree's `-X`
+//! rutree2 --output xml
+//!
+//! # Self-contained HTML page with collapsible directories
+//! rutree2 --output html > tree.html
+//!
+//! # Markdown nested list with relative links, or a fenced tree block
+//! rutree2 --output markdown
+//! rutree2 --output markdown --markdown-style fenced
+//!
+//! # Graphviz diagram, with symlinks drawn as dashed edges
+//! rutree2 --output dot | dot -Tsvg > tree.svg
+//!
+//! # Mermaid flowchart or mindmap, and PlantUML work breakdown structure
+//! rutree2 --output mermaid
+//! rutree2 --output mermaid-mindmap
+//! rutree2 --output plantuml
+//!
+//! # One row per entry for spreadsheets and databases
+//! rutree2 --output csv > inventory.csv
+//!
+//! # Stream one JSON record per entry while walking very large trees
+//! rutree2 --output ndjson /mnt/storage | jq -c 'select(.type == "socket")'
+//!
 //! # Combine options
 //! rutree2 --all --depth 3 --interactive /path/to/directory
 //! ```
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use colored::{ColoredString, Colorize as ColoredColorize};
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::io::IsTerminal;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // For interactive mode
 use crossterm::event::{self, Event, KeyCode};
@@ -67,32 +170,33 @@ use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, List, ListItem, ListState};
 
-// Tree drawing constants for interactive mode
-const TREE_LAST: &str = "└── ";
-const TREE_BRANCH: &str = "├── ";
-const TREE_SPACE: &str = "    ";
-const TREE_VERTICAL: &str = "│   ";
 // Unix permission bit constants for file mode checking
-#[cfg(unix)]
 const MODE_STICKY_BIT: u32 = 0o1000; // Sticky bit (e.g., /tmp directories)
-#[cfg(unix)]
 const MODE_SETUID: u32 = 0o4000; // Set user ID on execution
-#[cfg(unix)]
 const MODE_SETGID: u32 = 0o2000; // Set group ID on execution
-#[cfg(unix)]
 const MODE_EXECUTABLE: u32 = 0o111; // User/group/other execute bits
-#[cfg(unix)]
 const MODE_WORLD_WRITABLE: u32 = 0o002; // World writable bit
 
 /// Command-line interface configuration for rutree2
 #[derive(Parser)]
 #[command(name = "rutree2")]
 #[command(about = "Display directory tree structure", long_about = None)]
+// -h shows human-readable sizes like GNU tree, so help is only available as --help
+#[command(disable_help_flag = true)]
 struct Cli {
-    /// Path to display (defaults to current directory)
+    /// Path to display (defaults to current directory); with --fromfile, the file listing
+    /// the paths ('.' or '-' for stdin)
     #[arg(default_value = ".")]
     path: PathBuf,
 
+    /// Read the paths to display from a listing instead of the filesystem
+    #[arg(long)]
+    fromfile: bool,
+
+    /// With --fromfile, paths are separated by NUL characters instead of newlines
+    #[arg(short = '0', long, requires = "fromfile")]
+    null: bool,
+
     /// Show hidden files
     #[arg(short = 'a', long)]
     all: bool,
@@ -101,10 +205,150 @@ struct Cli {
     #[arg(short = 'd', long)]
     depth: Option<usize>,
 
+    /// List only files matching the glob pattern (repeatable; '|' separates alternatives)
+    #[arg(short = 'P', long = "pattern", value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Exclude entries matching the glob pattern; excluded directories are not read
+    /// (repeatable; '|' separates alternatives)
+    #[arg(short = 'I', long = "exclude", value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Match -P/-I patterns case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// List only files matching the regular expression (repeatable)
+    #[arg(long, value_name = "REGEX", value_parser = parse_regex)]
+    match_regex: Vec<Regex>,
+
+    /// Exclude entries matching the regular expression; excluded directories are not
+    /// read (repeatable)
+    #[arg(long, value_name = "REGEX", value_parser = parse_regex)]
+    exclude_regex: Vec<Regex>,
+
+    /// What --match-regex and --exclude-regex are matched against: the entry name, or
+    /// its path relative to the root (name, path)
+    #[arg(long, default_value = "name", value_parser = validate_regex_on)]
+    regex_on: String,
+
+    /// List only entries of the given types, like find -type (d, f, l, s, p, b, c;
+    /// comma-separated or repeatable). Other directories are kept to show the structure
+    #[arg(long = "type", value_name = "TYPE", value_delimiter = ',', value_parser = parse_entry_kind)]
+    types: Vec<EntryKind>,
+
+    /// List only regular files of at least this size (e.g. 500K, 10M, 1.5GiB, 2GB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// List only regular files of at most this size (e.g. 500K, 10M, 1.5GiB, 2GB)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// List only files whose --time-field is newer than a duration ago (30m, 3d, 2w), a
+    /// UTC date (2024-05-01, 2024-05-01T12:00:00) or the mtime of a reference file
+    #[arg(long, value_name = "TIME", value_parser = parse_time_spec)]
+    newer_than: Option<SystemTime>,
+
+    /// List only files whose --time-field is older than a duration ago (30m, 3d, 2w), a
+    /// UTC date (2024-05-01, 2024-05-01T12:00:00) or the mtime of a reference file
+    #[arg(long, value_name = "TIME", value_parser = parse_time_spec)]
+    older_than: Option<SystemTime>,
+
+    /// Timestamp used by --newer-than and --older-than (mtime, ctime, atime)
+    #[arg(long, default_value = "mtime", value_parser = parse_time_field)]
+    time_field: TimeField,
+
+    /// List only files owned by the user, given by name or id (repeatable)
+    #[arg(long, value_name = "USER", value_parser = parse_user)]
+    user: Vec<u32>,
+
+    /// List only files owned by the group, given by name or id (repeatable)
+    #[arg(long, value_name = "GROUP", value_parser = parse_group)]
+    group: Vec<u32>,
+
+    /// List only files whose permissions match, like find -perm: octal MODE (exact),
+    /// -MODE (all bits set), /MODE (any bit set), or setuid, setgid, sticky, executable,
+    /// world-writable (comma-separated or repeatable; any may match)
+    #[arg(long, value_name = "MODE", value_delimiter = ',', allow_hyphen_values = true, value_parser = parse_perm)]
+    perm: Vec<PermMatch>,
+
+    /// List only text files with a line matching the regular expression
+    #[arg(long, value_name = "REGEX", value_parser = parse_regex)]
+    contains: Option<Regex>,
+
+    /// With --contains, show the number of matching lines next to each file
+    #[arg(long, requires = "contains")]
+    count: bool,
+
+    /// Show at most N entries per directory, summarizing the others in a
+    /// "… K more entries" line
+    #[arg(long, value_name = "N")]
+    filelimit: Option<usize>,
+
+    /// Remove directories that are empty, including those emptied by the filters
+    #[arg(long)]
+    prune: bool,
+
+    /// Hide entries ignored by .gitignore, .ignore, .rutreeignore, .git/info/exclude
+    /// and git's core.excludesFile
+    #[arg(long, conflicts_with = "fromfile")]
+    gitignore: bool,
+
     /// Use colors to distinguish file types and permissions (auto, always, never)
     #[arg(short = 'C', long, default_value = "auto", value_parser = validate_color)]
     color: String,
 
+    /// Line-drawing charset (utf8, ascii, heavy, rounded, or custom:BRANCH,LAST,VERTICAL)
+    #[arg(long, default_value = "utf8", value_parser = parse_charset)]
+    charset: Charset,
+
+    /// Show the size of each entry in bytes
+    #[arg(short = 's', long = "size")]
+    size: bool,
+
+    /// Show sizes in human-readable form, in powers of 1024 (e.g. 4.0K, 12M)
+    #[arg(short = 'h', long = "human")]
+    human: bool,
+
+    /// Like -h, but in powers of 1000
+    #[arg(long)]
+    si: bool,
+
+    /// Instead of the tree, report the number and total size of files per extension and
+    /// per category (archive, image, media, other), as text or with -o json, csv or tsv
+    #[arg(long, conflicts_with = "interactive")]
+    stats: bool,
+
+    /// Don't print the summary of directories, files, symlinks and size after the tree
+    #[arg(long)]
+    noreport: bool,
+
+    /// Show ls -l style metadata before each name: mode, owner, group and mtime
+    #[arg(short = 'l', long)]
+    long: bool,
+
+    /// Metadata columns to show, in order (mode, owner, group, mtime, inode, links;
+    /// comma-separated)
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',', value_parser = parse_long_column)]
+    columns: Vec<LongColumn>,
+
+    /// Show the cumulative size of each directory; apparent sizes by default, or
+    /// allocated disk blocks with --du=allocated (implies -s)
+    #[arg(
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "apparent",
+        value_parser = parse_du_mode
+    )]
+    du: Option<DuMode>,
+
+    /// Print help
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+
     /// Interactive collapsible/expandable tree view
     #[arg(
         short = 'i',
@@ -112,6 +356,15 @@ struct Cli {
         help = "Interactive collapsible/expandable tree view"
     )]
     interactive: bool,
+
+    /// Output format (tree, json, ndjson, xml, html, markdown, dot, mermaid, mermaid-mindmap,
+    /// plantuml, csv, tsv)
+    #[arg(short = 'o', long, default_value = "tree", value_parser = validate_output)]
+    output: String,
+
+    /// Markdown output style: nested bullet list with links, or fenced tree (list, fenced)
+    #[arg(long, default_value = "list", value_parser = validate_markdown_style)]
+    markdown_style: String,
 }
 
 /// Validates the color argument value
@@ -125,623 +378,4712 @@ fn validate_color(s: &str) -> Result<String, String> {
     }
 }
 
-/// Main entry point for the rutree2 application.
-///
-/// Parses command-line arguments and initiates the directory tree display.
-fn main() {
-    let cli = Cli::parse();
+/// Options controlling which entries the directory walk visits
+#[derive(Debug)]
+struct WalkOptions {
+    /// Root of the walk; patterns containing `/` match paths relative to it
+    root: PathBuf,
+    show_hidden: bool,
+    max_depth: Option<usize>,
+    include: Vec<GlobPattern>,
+    exclude: Vec<GlobPattern>,
+    match_regex: Vec<Regex>,
+    exclude_regex: Vec<Regex>,
+    /// Match the regexes against the relative path instead of the name
+    regex_on_path: bool,
+    /// Entry kinds to list; empty lists every kind
+    types: Vec<EntryKind>,
+    /// Inclusive size bounds for regular files, in bytes
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    /// Exclusive time bounds for non-directories, on `time_field`
+    newer_than: Option<SystemTime>,
+    older_than: Option<SystemTime>,
+    time_field: TimeField,
+    /// Owner ids, group ids and permission tests; an entry must match one
+    /// of each non-empty list
+    users: Vec<u32>,
+    groups: Vec<u32>,
+    perms: Vec<PermMatch>,
+    /// Content filter for regular files, and whether to count matching lines
+    contains: Option<Regex>,
+    count_matches: bool,
+    /// Line counts found while filtering with `--count`, so that each file
+    /// is only read once. Taken by [`WalkOptions::match_count`] when the
+    /// entry becomes a node, or dropped by [`WalkOptions::forget_matches`].
+    match_counts: RefCell<HashMap<PathBuf, usize>>,
+    /// Maximum number of entries listed per directory
+    file_limit: Option<usize>,
+    ignore_files: Option<IgnoreFiles>,
+    /// Drop directories without any remaining children
+    prune: bool,
+}
 
-    // Configure colored output based on --color option
-    match cli.color.as_str() {
-        "never" => colored::control::set_override(false),
-        "always" => colored::control::set_override(true),
-        _ => {
-            // Auto-detect: use colors if stdout is a terminal
-            if !std::io::stdout().is_terminal() {
-                colored::control::set_override(false);
+impl WalkOptions {
+    /// Options that show every non-hidden entry below `root`
+    fn new(root: &Path) -> Self {
+        WalkOptions {
+            root: root.to_path_buf(),
+            show_hidden: false,
+            max_depth: None,
+            include: vec![],
+            exclude: vec![],
+            match_regex: vec![],
+            exclude_regex: vec![],
+            regex_on_path: false,
+            types: vec![],
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            time_field: TimeField::Modified,
+            users: vec![],
+            groups: vec![],
+            perms: vec![],
+            contains: None,
+            count_matches: false,
+            match_counts: RefCell::default(),
+            file_limit: None,
+            ignore_files: None,
+            prune: false,
+        }
+    }
+
+    fn from_cli(cli: &Cli) -> Self {
+        let compile = |patterns: &[String]| -> Vec<GlobPattern> {
+            patterns
+                .iter()
+                .flat_map(|p| p.split('|'))
+                .filter(|p| !p.is_empty())
+                .map(|p| GlobPattern::new(p, cli.ignore_case))
+                .collect()
+        };
+        WalkOptions {
+            show_hidden: cli.all,
+            max_depth: cli.depth,
+            include: compile(&cli.include),
+            exclude: compile(&cli.exclude),
+            match_regex: cli.match_regex.clone(),
+            exclude_regex: cli.exclude_regex.clone(),
+            regex_on_path: cli.regex_on == "path",
+            types: cli.types.clone(),
+            min_size: cli.min_size,
+            max_size: cli.max_size,
+            newer_than: cli.newer_than,
+            older_than: cli.older_than,
+            time_field: cli.time_field,
+            users: cli.user.clone(),
+            groups: cli.group.clone(),
+            perms: cli.perm.clone(),
+            contains: cli.contains.clone(),
+            count_matches: cli.count,
+            file_limit: cli.filelimit,
+            ignore_files: cli.gitignore.then(|| IgnoreFiles::new(&cli.path)),
+            prune: cli.prune,
+            ..WalkOptions::new(&cli.path)
+        }
+    }
+
+    /// Whether the entry at `rel_path` (relative to the root, `/`-separated)
+    /// is part of the walk.
+    ///
+    /// `is_dir` follows symlinks while `metadata` does not. Hidden and
+    /// excluded entries are dropped whatever their type, so an excluded
+    /// directory is never read. Include patterns, types and metadata filters
+    /// only apply to non-directories, which keeps the directories leading to
+    /// matches. Metadata filters pass entries without metadata, such as the
+    /// paths of a `--fromfile` listing.
+    fn keeps(&self, rel_path: &str, is_dir: bool, metadata: Option<&fs::Metadata>) -> bool {
+        let name = rel_path.rsplit('/').next().unwrap_or(rel_path);
+        if !self.show_hidden && name.starts_with('.') {
+            return false;
+        }
+        if self.exclude.iter().any(|p| p.matches(rel_path, is_dir)) {
+            return false;
+        }
+        let regex_subject = if self.regex_on_path { rel_path } else { name };
+        if self.exclude_regex.iter().any(|r| r.is_match(regex_subject)) {
+            return false;
+        }
+        if !is_dir
+            && !self.match_regex.is_empty()
+            && !self.match_regex.iter().any(|r| r.is_match(regex_subject))
+        {
+            return false;
+        }
+        if !is_dir
+            && !self.include.is_empty()
+            && !self.include.iter().any(|p| p.matches(rel_path, is_dir))
+        {
+            return false;
+        }
+        if let Some(ignore_files) = &self.ignore_files
+            && ignore_files.is_ignored(rel_path, is_dir)
+        {
+            return false;
+        }
+        let kind = EntryKind::of(metadata);
+        let kind_selected = self.types.is_empty() || self.types.contains(&kind);
+        // A link to a directory is a link for `--type`, so it is neither
+        // listed nor descended into unless links are selected
+        if is_dir {
+            return kind != EntryKind::Symlink || kind_selected;
+        }
+        if !kind_selected {
+            return false;
+        }
+        if let Some(metadata) = metadata
+            && kind == EntryKind::File
+        {
+            let size = metadata.len();
+            if self.min_size.is_some_and(|min| size < min)
+                || self.max_size.is_some_and(|max| size > max)
+            {
+                return false;
+            }
+        }
+        if let Some(metadata) = metadata
+            && (self.newer_than.is_some() || self.older_than.is_some())
+        {
+            let Some(time) = self.time_field.of(metadata) else {
+                return false;
+            };
+            if self.newer_than.is_some_and(|bound| time <= bound)
+                || self.older_than.is_some_and(|bound| time >= bound)
+            {
+                return false;
+            }
+        }
+        if let Some(metadata) = metadata
+            && !self.keeps_owner_and_mode(metadata, kind)
+        {
+            return false;
+        }
+        // Checked last since it reads the file. Only regular files are read:
+        // opening a FIFO or a device could block or never end.
+        if let Some(regex) = &self.contains
+            && metadata.is_some()
+        {
+            if kind != EntryKind::File {
+                return false;
+            }
+            let path = self.root.join(rel_path);
+            let count = if self.count_matches {
+                let count = count_matching_lines(&path, regex, usize::MAX);
+                self.match_counts.borrow_mut().insert(path, count);
+                count
+            } else {
+                count_matching_lines(&path, regex, 1)
+            };
+            if count == 0 {
+                return false;
             }
         }
+        true
     }
 
-    // Validate the path exists
-    if !cli.path.exists() {
-        eprintln!("Error: Path '{}' does not exist", cli.path.display());
-        std::process::exit(1);
+    /// Whether an entry passes the `--user`, `--group` and `--perm` filters.
+    ///
+    /// The permissions of symbolic links themselves are meaningless, so they
+    /// never match `--perm`. Without Unix ownership and modes, nothing matches.
+    fn keeps_owner_and_mode(&self, metadata: &fs::Metadata, kind: EntryKind) -> bool {
+        if self.users.is_empty() && self.groups.is_empty() && self.perms.is_empty() {
+            return true;
+        }
+        let Some((uid, gid, mode)) = unix_owner_and_mode(metadata) else {
+            return false;
+        };
+        (self.users.is_empty() || self.users.contains(&uid))
+            && (self.groups.is_empty() || self.groups.contains(&gid))
+            && (self.perms.is_empty()
+                || kind != EntryKind::Symlink && self.perms.iter().any(|p| p.matches(mode)))
     }
 
-    if cli.interactive {
-        if let Err(e) = interactive_tree(&cli.path, cli.all, cli.depth) {
-            eprintln!("Interactive mode error: {}", e);
-            std::process::exit(1);
+    /// Whether a directory itself matches `--user`, `--group` and `--perm`,
+    /// which keeps it even when `--prune` finds it empty
+    fn matches_dir(&self, node: &TreeNode) -> bool {
+        (!self.users.is_empty() || !self.groups.is_empty() || !self.perms.is_empty())
+            && node
+                .metadata
+                .as_ref()
+                .is_some_and(|metadata| self.keeps_owner_and_mode(metadata, node.kind))
+    }
+
+    /// Number of lines of `node` matching `--contains`, when `--count` is set
+    fn match_count(&self, node: &TreeNode) -> Option<usize> {
+        match &self.contains {
+            Some(regex) if self.count_matches && node.kind == EntryKind::File => Some(
+                self.match_counts
+                    .borrow_mut()
+                    .remove(&node.path)
+                    .unwrap_or_else(|| count_matching_lines(&node.path, regex, usize::MAX)),
+            ),
+            _ => None,
         }
-    } else {
-        match display_tree(&cli.path, cli.all, cli.depth, "", 0) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!("Error reading directory '{}': {}", cli.path.display(), e);
-                std::process::exit(1);
+    }
+
+    /// Drops the line counts of entries that passed [`WalkOptions::keeps`]
+    /// but don't become nodes
+    fn forget_matches(&self, entries: &[fs::DirEntry]) {
+        if self.count_matches {
+            let mut counts = self.match_counts.borrow_mut();
+            for entry in entries {
+                counts.remove(&entry.path());
             }
         }
     }
+
+    /// Truncates the entries of a directory to `--filelimit`, returning the
+    /// ones left out
+    fn limit_entries<T>(&self, entries: &mut Vec<T>) -> Vec<T> {
+        match self.file_limit {
+            Some(limit) if entries.len() > limit => entries.split_off(limit),
+            _ => vec![],
+        }
+    }
+
+    /// Whether the children of an entry at `depth` are read
+    fn descends(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|m| depth < m)
+    }
+
+    /// Whether `node` is removed by `--prune`.
+    ///
+    /// Directories at the depth limit are kept: they were never read, so
+    /// they are not known to be empty. So are the directories matching the
+    /// owner and permission filters themselves.
+    fn prunes(&self, node: &TreeNode) -> bool {
+        self.prune
+            && node.is_dir
+            && node.children.is_empty()
+            && node.elided == 0
+            && self.descends(node.depth)
+            && !self.matches_dir(node)
+    }
 }
 
-/// Interactive collapsible/expandable tree using ratatui
-fn interactive_tree(
-    path: &Path,
-    show_hidden: bool,
-    max_depth: Option<usize>,
-) -> std::io::Result<()> {
-    enable_raw_mode()?;
-    let mut stdout = std::io::stdout();
-    crossterm::execute!(stdout, crossterm::terminal::EnterAlternateScreen)?;
-    let backend = ratatui::backend::CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// Ignore rules from `.gitignore`-style files, as used by `--gitignore`.
+///
+/// Rules from the files in each directory are loaded the first time an
+/// entry in that directory is checked. Files in the directories between
+/// the enclosing git repository and the walk root apply as well.
+#[derive(Debug)]
+struct IgnoreFiles {
+    /// Canonical walk root
+    root: PathBuf,
+    /// Topmost directory whose ignore files apply: the git repository
+    /// root, or the walk root outside of a repository
+    top: PathBuf,
+    /// Rules from `core.excludesFile` and `.git/info/exclude`
+    global: Vec<IgnoreRule>,
+    per_dir: RefCell<HashMap<PathBuf, Rc<Vec<IgnoreRule>>>>,
+}
 
-    // Legend overlay content and size
-    let legend_lines = [
-        "↑/↓: Move",
-        "→/Enter: Expand/Enter Dir",
-        "←: Collapse",
-        "q: Quit",
-    ];
-    let legend_width = legend_lines.iter().map(|l| l.len()).max().unwrap_or(12) as u16 + 4;
-    let legend_height = legend_lines.len() as u16 + 2;
+/// Ignore files read in every directory, from lowest to highest precedence
+const IGNORE_FILE_NAMES: &[&str] = &[".gitignore", ".ignore", ".rutreeignore"];
 
-    // Build initial tree state
-    let mut tree = TreeNode::from_path(path, show_hidden, max_depth, 0)?;
-    let mut state = ListState::default();
-    state.select(Some(0));
+#[derive(Debug)]
+struct IgnoreRule {
+    pattern: GlobPattern,
+    negated: bool,
+    /// Directory the pattern is relative to
+    base: PathBuf,
+}
 
-    let mut flat = tree.flatten();
+impl IgnoreFiles {
+    fn new(root: &Path) -> Self {
+        let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let repo = root.ancestors().find(|dir| dir.join(".git").exists());
+        let top = repo.unwrap_or(&root).to_path_buf();
 
-    loop {
-        terminal.draw(|f| {
-            let size = f.area();
-            let legend_x = size.x + size.width.saturating_sub(legend_width);
-            let legend_y = size.y;
-            let legend_area = ratatui::layout::Rect {
-                x: legend_x,
-                y: legend_y,
-                width: legend_width,
-                height: legend_height,
-            };
+        let mut global = vec![];
+        if let Some(path) = global_excludes_file(repo) {
+            global.extend(parse_ignore_file(&path, &top));
+        }
+        if let Some(repo) = repo {
+            let exclude = repo.join(".git").join("info").join("exclude");
+            global.extend(parse_ignore_file(&exclude, repo));
+        }
+        IgnoreFiles {
+            root,
+            top,
+            global,
+            per_dir: RefCell::new(HashMap::new()),
+        }
+    }
 
-            let selected_idx = state.selected().unwrap_or(0);
-            let items: Vec<ListItem> = flat
-                .iter()
-                .enumerate()
-                .map(|(i, (prefix, node))| {
-                    let mut label = format!("{}{}", prefix, node.display_name());
-                    if node.is_dir && !node.expanded {
-                        label.push_str(" [+]");
-                    } else if node.is_dir && node.expanded {
-                        label.push_str(" [-]");
-                    }
-                    if i == selected_idx {
-                        ListItem::new(label).style(
-                            ratatui::style::Style::default()
-                                .fg(ratatui::style::Color::Black)
-                                .bg(ratatui::style::Color::Yellow)
-                                .add_modifier(ratatui::style::Modifier::BOLD),
-                        )
-                    } else {
-                        ListItem::new(label)
-                    }
-                })
-                .collect();
-            let list = List::new(items).block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("rutree2 (interactive)"),
-            );
-            f.render_stateful_widget(list, size, &mut state);
-            let legend_items: Vec<ListItem> =
-                legend_lines.iter().map(|l| ListItem::new(*l)).collect();
-            let legend = List::new(legend_items)
-                .block(Block::default().borders(Borders::ALL).title("Legend"));
-            f.render_widget(legend, legend_area);
-        })?;
+    /// Whether the entry at `rel_path` (relative to the walk root) is ignored.
+    ///
+    /// Like git, the last matching rule wins, rules from deeper directories
+    /// override shallower ones, and `.git` itself is always ignored.
+    fn is_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        let path = self.root.join(rel_path);
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let mut dirs: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.top))
+            .collect();
+        dirs.reverse();
 
-        if let Event::Key(key) = event::read()? {
-            match key.code {
-                KeyCode::Char('q') => break,
-                KeyCode::Down => {
-                    let sel = state.selected().unwrap_or(0);
-                    let new_sel = if sel + 1 < flat.len() { sel + 1 } else { sel };
-                    state.select(Some(new_sel));
-                }
-                KeyCode::Up => {
-                    let sel = state.selected().unwrap_or(0);
-                    let new_sel = if sel > 0 { sel - 1 } else { 0 };
-                    state.select(Some(new_sel));
-                }
-                KeyCode::Right | KeyCode::Enter => {
-                    let sel = state.selected().unwrap_or(0);
-                    if let Some(node) = get_mut_node(&mut tree, sel)
-                        && node.is_dir
-                    {
-                        node.expanded = !node.expanded;
-                        flat = tree.flatten();
-                        let new_sel = sel.min(flat.len().saturating_sub(1));
-                        state.select(Some(new_sel));
-                    }
-                }
-                KeyCode::Left => {
-                    let sel = state.selected().unwrap_or(0);
-                    if let Some(node) = get_mut_node(&mut tree, sel)
-                        && node.is_dir
-                        && node.expanded
-                    {
-                        node.expanded = false;
-                        flat = tree.flatten();
-                        let new_sel = sel.min(flat.len().saturating_sub(1));
-                        state.select(Some(new_sel));
-                    }
+        let mut ignored = false;
+        let mut apply = |rules: &[IgnoreRule]| {
+            for rule in rules {
+                let Ok(rel) = path.strip_prefix(&rule.base) else {
+                    continue;
+                };
+                if rule
+                    .pattern
+                    .matches(&relative_path(Path::new(""), rel), is_dir)
+                {
+                    ignored = !rule.negated;
                 }
-                _ => {}
             }
+        };
+        apply(&self.global);
+        for dir in dirs {
+            apply(&self.rules_in(dir));
         }
+        ignored
     }
 
-    disable_raw_mode()?;
-    crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen)?;
-    Ok(())
+    fn rules_in(&self, dir: &Path) -> Rc<Vec<IgnoreRule>> {
+        let mut per_dir = self.per_dir.borrow_mut();
+        let rules = per_dir.entry(dir.to_path_buf()).or_insert_with(|| {
+            let rules = IGNORE_FILE_NAMES
+                .iter()
+                .flat_map(|name| parse_ignore_file(&dir.join(name), dir))
+                .collect();
+            Rc::new(rules)
+        });
+        Rc::clone(rules)
+    }
 }
 
-/// Tree node for interactive mode
-#[derive(Debug, Clone)]
-struct TreeNode {
-    name: String,
-    is_dir: bool,
-    expanded: bool,
-    children: Vec<TreeNode>,
-    depth: usize,
+/// Parses a `.gitignore`-style file; a missing or unreadable file has no rules
+fn parse_ignore_file(path: &Path, base: &Path) -> Vec<IgnoreRule> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    contents
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            // `\!` and `\#` stay escaped and match literally in the glob
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, line),
+            };
+            IgnoreRule {
+                pattern: GlobPattern::new(pattern, false),
+                negated,
+                base: base.to_path_buf(),
+            }
+        })
+        .collect()
 }
 
-impl TreeNode {
-    fn from_path(
-        path: &Path,
-        show_hidden: bool,
-        max_depth: Option<usize>,
-        depth: usize,
-    ) -> std::io::Result<Self> {
-        let name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or(".")
-            .to_string();
-        let is_dir = path.is_dir();
-        let mut node = TreeNode {
-            name,
-            is_dir,
-            expanded: depth == 0, // root expanded
-            children: vec![],
-            depth,
+/// Locates git's global excludes file: `core.excludesFile` from the
+/// repository or user configuration, falling back to `$XDG_CONFIG_HOME/git/ignore`
+fn global_excludes_file(repo: Option<&Path>) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let xdg_config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+
+    // Later files take precedence, like git's system < global < local order
+    let configs = [
+        xdg_config
+            .as_ref()
+            .map(|dir| dir.join("git").join("config")),
+        home.as_ref().map(|home| home.join(".gitconfig")),
+        repo.map(|repo| repo.join(".git").join("config")),
+    ];
+    let configured = configs
+        .iter()
+        .flatten()
+        .filter_map(|config| fs::read_to_string(config).ok())
+        .filter_map(|contents| git_config_excludes_file(&contents))
+        .next_back();
+
+    match configured {
+        Some(path) => match (path.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => Some(home.join(rest)),
+            _ => Some(PathBuf::from(path)),
+        },
+        None => xdg_config.map(|dir| dir.join("git").join("ignore")),
+    }
+}
+
+/// Extracts `core.excludesFile` from the contents of a git config file
+fn git_config_excludes_file(contents: &str) -> Option<String> {
+    let mut in_core = false;
+    let mut value = None;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_core = line.eq_ignore_ascii_case("[core]");
+        } else if in_core
+            && let Some((key, val)) = line.split_once('=')
+            && key.trim().eq_ignore_ascii_case("excludesfile")
+        {
+            value = Some(val.trim().trim_matches('"').to_string());
+        }
+    }
+    value
+}
+
+/// Size of the prefix of a file searched for NUL bytes to detect binary files, like grep
+const BINARY_CHECK_LEN: usize = 8192;
+
+/// Counts the lines of a text file matching `regex`, stopping at `limit`.
+///
+/// Binary files, recognized by a NUL byte near the start, and unreadable
+/// files have no matching lines.
+fn count_matching_lines(path: &Path, regex: &Regex, limit: usize) -> usize {
+    let Ok(mut file) = fs::File::open(path) else {
+        return 0;
+    };
+    let mut prefix = Vec::with_capacity(BINARY_CHECK_LEN);
+    if (&mut file)
+        .take(BINARY_CHECK_LEN as u64)
+        .read_to_end(&mut prefix)
+        .is_err()
+        || prefix.contains(&0)
+    {
+        return 0;
+    }
+    // Lines are streamed, so only the lines up to the last match needed are read
+    BufReader::new(io::Cursor::new(prefix).chain(file))
+        .split(b'\n')
+        .map_while(Result::ok)
+        .filter(|line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            regex.is_match(&String::from_utf8_lossy(line))
+        })
+        .take(limit)
+        .count()
+}
+
+/// Permission test of a `--perm` argument, like `find -perm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PermMatch {
+    /// The permission bits are exactly the mode
+    Exact(u32),
+    /// All the bits of the mode are set
+    All(u32),
+    /// Any bit of the mode is set (or the mode is 0)
+    Any(u32),
+}
+
+impl PermMatch {
+    fn matches(self, mode: u32) -> bool {
+        let perm = mode & 0o7777;
+        match self {
+            PermMatch::Exact(bits) => perm == bits,
+            PermMatch::All(bits) => perm & bits == bits,
+            PermMatch::Any(bits) => bits == 0 || perm & bits != 0,
+        }
+    }
+}
+
+/// Reads the owner uid, group gid and mode of an entry on Unix
+#[cfg(unix)]
+fn unix_owner_and_mode(metadata: &fs::Metadata) -> Option<(u32, u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.uid(), metadata.gid(), metadata.mode()))
+}
+
+#[cfg(not(unix))]
+fn unix_owner_and_mode(_metadata: &fs::Metadata) -> Option<(u32, u32, u32)> {
+    None
+}
+
+/// File timestamp selected with `--time-field`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeField {
+    /// Last modification of the contents (mtime)
+    Modified,
+    /// Last status change (ctime); the creation time on non-Unix platforms
+    Changed,
+    /// Last access (atime)
+    Accessed,
+}
+
+impl TimeField {
+    /// Reads this timestamp from `metadata`, if the platform provides it
+    fn of(self, metadata: &fs::Metadata) -> Option<SystemTime> {
+        match self {
+            TimeField::Modified => metadata.modified().ok(),
+            TimeField::Accessed => metadata.accessed().ok(),
+            #[cfg(unix)]
+            TimeField::Changed => {
+                use std::os::unix::fs::MetadataExt;
+                let since_epoch = Duration::new(
+                    metadata.ctime().unsigned_abs(),
+                    metadata.ctime_nsec() as u32,
+                );
+                if metadata.ctime() >= 0 {
+                    UNIX_EPOCH.checked_add(since_epoch)
+                } else {
+                    UNIX_EPOCH.checked_sub(since_epoch)
+                }
+            }
+            #[cfg(not(unix))]
+            TimeField::Changed => metadata.created().ok(),
+        }
+    }
+}
+
+/// Converts `path` to a `/`-separated string relative to `root`
+fn relative_path(root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let parts: Vec<_> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    parts.join("/")
+}
+
+/// A compiled shell glob pattern.
+///
+/// Supports `*` and `?` (which do not cross `/`), `**` (which does),
+/// character classes such as `[a-z]` or `[!0-9]`, and `\` escapes. A
+/// pattern containing `/` is matched against the whole relative path,
+/// otherwise against the entry name. A trailing `/` restricts the pattern
+/// to directories.
+#[derive(Debug, Clone)]
+struct GlobPattern {
+    glob: Vec<char>,
+    match_path: bool,
+    dir_only: bool,
+    ignore_case: bool,
+}
+
+impl GlobPattern {
+    fn new(pattern: &str, ignore_case: bool) -> Self {
+        let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let match_path = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        let pattern = if ignore_case {
+            pattern.to_lowercase()
+        } else {
+            pattern.to_string()
         };
-        if is_dir && max_depth.is_none_or(|m| depth < m) {
-            let mut entries: Vec<_> = fs::read_dir(path)?
-                .filter_map(Result::ok)
-                .filter(|entry| {
-                    if !show_hidden && let Some(name) = entry.file_name().to_str() {
-                        return !name.starts_with('.');
-                    }
-                    true
-                })
-                .collect();
-            entries.sort_by_key(|entry| entry.file_name());
-            for entry in entries {
-                let child = TreeNode::from_path(&entry.path(), show_hidden, max_depth, depth + 1)?;
-                node.children.push(child);
+        GlobPattern {
+            glob: pattern.chars().collect(),
+            match_path,
+            dir_only,
+            ignore_case,
+        }
+    }
+
+    /// Whether the entry at `rel_path` (relative to the root, `/`-separated) matches
+    fn matches(&self, rel_path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let subject = if self.match_path {
+            rel_path
+        } else {
+            rel_path.rsplit('/').next().unwrap_or(rel_path)
+        };
+        let text: Vec<char> = if self.ignore_case {
+            subject.to_lowercase().chars().collect()
+        } else {
+            subject.chars().collect()
+        };
+        glob_match(&self.glob, &text)
+    }
+}
+
+/// Matches `text` against a glob, see [`GlobPattern`] for the syntax
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            let rest = &glob[2..];
+            if let Some(rest) = rest.strip_prefix(&['/']) {
+                // `**/` matches zero or more whole directories
+                glob_match(rest, text)
+                    || (0..text.len()).any(|i| text[i] == '/' && glob_match(rest, &text[i + 1..]))
+            } else {
+                (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
             }
         }
-        Ok(node)
+        Some('*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(&glob[1..], &text[i..])),
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match(&glob[1..], &text[1..]),
+        Some('[') => match match_class(&glob[1..], text.first().copied()) {
+            Some((matched, consumed)) => matched && glob_match(&glob[1 + consumed..], &text[1..]),
+            // No closing bracket: treat `[` as a literal character
+            None => text.first() == Some(&'[') && glob_match(&glob[1..], &text[1..]),
+        },
+        Some('\\') if glob.len() > 1 => {
+            text.first() == Some(&glob[1]) && glob_match(&glob[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match(&glob[1..], &text[1..]),
     }
+}
 
-    fn display_name(&self) -> String {
-        if self.is_dir {
-            format!("{}/", self.name)
+/// Matches a character against the class starting right after a `[`.
+///
+/// Returns whether `c` matched and how many glob characters the class
+/// spans including the closing `]`, or `None` if the class is unterminated.
+fn match_class(class: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let negated = matches!(class.first(), Some('!') | Some('^'));
+    let mut i = usize::from(negated);
+    let mut matched = false;
+    let mut first = true;
+    while i < class.len() {
+        let start = class[i];
+        if start == ']' && !first {
+            let matched = c.is_some_and(|c| c != '/') && (matched != negated);
+            return Some((matched, i + 1));
+        }
+        first = false;
+        if class.get(i + 1) == Some(&'-') && class.get(i + 2).is_some_and(|&end| end != ']') {
+            let end = class[i + 2];
+            matched |= c.is_some_and(|c| start <= c && c <= end);
+            i += 3;
         } else {
-            self.name.clone()
+            matched |= c == Some(start);
+            i += 1;
         }
     }
+    None
+}
 
-    /// Flatten the tree for display, returning (prefix, &TreeNode)
-    fn flatten(&self) -> Vec<(String, TreeNode)> {
-        let mut out = vec![];
-        self.flatten_inner("", &mut out, true);
-        out
+/// How sizes are shown in the size column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SizeFormat {
+    /// Exact number of bytes
+    Bytes,
+    /// Human-readable in powers of 1024 (`-h`)
+    Binary,
+    /// Human-readable in powers of 1000 (`--si`)
+    Decimal,
+}
+
+impl SizeFormat {
+    fn format(self, bytes: u64) -> String {
+        match self {
+            SizeFormat::Bytes => format!("{:>11}", bytes),
+            SizeFormat::Binary => format!("{:>4}", human_size(bytes, 1024)),
+            SizeFormat::Decimal => format!("{:>4}", human_size(bytes, 1000)),
+        }
     }
+}
 
-    fn flatten_inner(&self, prefix: &str, out: &mut Vec<(String, TreeNode)>, is_last: bool) {
-        let mut this_prefix = prefix.to_string();
-        if self.depth > 0 {
-            this_prefix += if is_last { TREE_LAST } else { TREE_BRANCH };
+/// Metadata column of the long listing (`-l` and `--columns`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LongColumn {
+    /// Type and permissions, like `drwxr-sr-x`
+    Mode,
+    Owner,
+    Group,
+    /// Modification time in UTC, like `2026-10-01 14:03`
+    Mtime,
+    Inode,
+    /// Number of hard links
+    Links,
+}
+
+impl LongColumn {
+    /// Name of the column in `--columns` and in structured output formats
+    fn as_str(self) -> &'static str {
+        match self {
+            LongColumn::Mode => "mode",
+            LongColumn::Owner => "owner",
+            LongColumn::Group => "group",
+            LongColumn::Mtime => "mtime",
+            LongColumn::Inode => "inode",
+            LongColumn::Links => "links",
         }
-        out.push((this_prefix.clone(), self.clone()));
-        if self.is_dir && self.expanded {
-            let n = self.children.len();
-            for (i, child) in self.children.iter().enumerate() {
-                let mut child_prefix = prefix.to_string();
-                if self.depth > 0 {
-                    child_prefix += if is_last { TREE_SPACE } else { TREE_VERTICAL };
+    }
+}
+
+/// Columns shown by `-l` without `--columns`
+const DEFAULT_LONG_COLUMNS: &[LongColumn] = &[
+    LongColumn::Mode,
+    LongColumn::Owner,
+    LongColumn::Group,
+    LongColumn::Mtime,
+];
+
+/// Formats the type and permission bits of an entry like `ls -l`
+fn mode_string(kind: EntryKind, mode: u32) -> String {
+    let mut out = String::with_capacity(10);
+    out.push(match kind {
+        EntryKind::Directory => 'd',
+        EntryKind::Symlink => 'l',
+        EntryKind::CharDevice => 'c',
+        EntryKind::BlockDevice => 'b',
+        EntryKind::Socket => 's',
+        EntryKind::Fifo => 'p',
+        EntryKind::File => '-',
+    });
+    // (special bit, its letter) for the user, group and other triplets
+    let specials = [
+        (MODE_SETUID, 's'),
+        (MODE_SETGID, 's'),
+        (MODE_STICKY_BIT, 't'),
+    ];
+    for (i, (special, letter)) in specials.into_iter().enumerate() {
+        let bits = mode >> (6 - 3 * i);
+        out.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => letter,
+            (false, true) => letter.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+/// Reads the inode number and hard link count of an entry on Unix
+#[cfg(unix)]
+fn unix_inode_and_links(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.ino(), metadata.nlink()))
+}
+
+#[cfg(not(unix))]
+fn unix_inode_and_links(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Reads the names and ids of a `name:password:id:...` database like
+/// /etc/passwd, in file order; empty if it can't be read
+fn read_id_database(database: &str) -> Vec<(String, u32)> {
+    let Ok(contents) = fs::read_to_string(database) else {
+        return vec![];
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((name.to_string(), id))
+        })
+        .collect()
+}
+
+/// Maps the ids of a database to their first name, like `ls -l`
+fn read_id_names(database: &str) -> HashMap<u32, String> {
+    let mut names = HashMap::new();
+    for (name, id) in read_id_database(database) {
+        names.entry(id).or_insert(name);
+    }
+    names
+}
+
+/// How `--du` measures the size of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DuMode {
+    /// Length of the contents, like `du --apparent-size`
+    Apparent,
+    /// Disk space of the allocated blocks, like `du`; the apparent size on
+    /// non-Unix platforms
+    Allocated,
+}
+
+impl DuMode {
+    fn size_of(self, metadata: &fs::Metadata) -> u64 {
+        match self {
+            DuMode::Apparent => metadata.len(),
+            #[cfg(unix)]
+            DuMode::Allocated => {
+                use std::os::unix::fs::MetadataExt;
+                metadata.blocks() * 512
+            }
+            #[cfg(not(unix))]
+            DuMode::Allocated => metadata.len(),
+        }
+    }
+}
+
+/// Adds up the sizes of the entries below a directory that isn't part of
+/// the tree, see [`TreeNode::compute_du`]. Unreadable directories count as empty.
+fn disk_usage_below(path: &Path, walk: &WalkOptions, mode: DuMode) -> u64 {
+    let Ok(entries) = read_entries(path, walk) else {
+        return 0;
+    };
+    walk.forget_matches(&entries);
+    entries
+        .iter()
+        .map(|entry| disk_usage(&entry.path(), walk, mode))
+        .sum()
+}
+
+/// Size of an entry that isn't part of the tree, including everything below it
+fn disk_usage(path: &Path, walk: &WalkOptions, mode: DuMode) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+    let below = if metadata.is_dir() {
+        disk_usage_below(path, walk, mode)
+    } else {
+        0
+    };
+    mode.size_of(&metadata) + below
+}
+
+/// Formats a size with a unit suffix like GNU tree -h: bytes below `base`,
+/// then one decimal below 10 (`4.0K`) and none above (`12M`)
+fn human_size(bytes: u64, base: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if bytes < base {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / base as f64;
+    let mut unit = 0;
+    while value.round() >= base as f64 && unit < UNITS.len() - 1 {
+        value /= base as f64;
+        unit += 1;
+    }
+    if value < 9.95 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// Formats a total size for the summary footer, like `1.2 GiB` or `512 bytes`
+fn format_total_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} {}", bytes, if bytes == 1 { "byte" } else { "bytes" });
+    }
+    let size = human_size(bytes, 1024);
+    let (value, unit) = size.split_at(size.len() - 1);
+    format!("{} {}iB", value, unit)
+}
+
+/// Totals reported in the footer below the text tree and in the reports of
+/// the XML and HTML outputs
+#[derive(Debug, Default, PartialEq, Eq)]
+struct TreeStats {
+    directories: usize,
+    /// Non-directory entries other than symlinks
+    files: usize,
+    symlinks: usize,
+    /// Entries left out by `--filelimit`
+    elided: usize,
+    /// Total size of the non-directories, unknown without metadata (`--fromfile`)
+    bytes: Option<u64>,
+}
+
+impl TreeStats {
+    /// Collects the totals of the entries below `node`, not counting itself
+    fn of(node: &TreeNode) -> Self {
+        let mut stats = TreeStats::default();
+        stats.add_children(node);
+        stats
+    }
+
+    fn add_children(&mut self, node: &TreeNode) {
+        self.elided += node.elided;
+        for child in &node.children {
+            if child.kind == EntryKind::Symlink {
+                self.symlinks += 1;
+            } else if child.is_dir {
+                self.directories += 1;
+            } else {
+                self.files += 1;
+            }
+            if !child.is_dir
+                && let Some(metadata) = &child.metadata
+            {
+                self.bytes = Some(self.bytes.unwrap_or(0) + metadata.len());
+            }
+            self.add_children(child);
+        }
+    }
+}
+
+impl std::fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = |count: usize, one: &str, many: &str| {
+            format!("{} {}", count, if count == 1 { one } else { many })
+        };
+        let mut parts = vec![
+            plural(self.directories, "directory", "directories"),
+            plural(self.files, "file", "files"),
+            plural(self.symlinks, "symlink", "symlinks"),
+        ];
+        if self.elided > 0 {
+            parts.push(format!("{} not shown", self.elided));
+        }
+        if let Some(bytes) = self.bytes {
+            parts.push(format_total_size(bytes));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Number and total size of the regular files per extension and per
+/// category, for `--stats`
+#[derive(Debug, Default)]
+struct ExtensionStats {
+    /// Lowercase extension, or empty for files without one
+    extensions: HashMap<String, (usize, u64)>,
+    /// Category from [`extension_class`]: archive, image, media or other
+    categories: HashMap<&'static str, (usize, u64)>,
+    total: (usize, u64),
+}
+
+impl ExtensionStats {
+    fn of(node: &TreeNode) -> Self {
+        let mut stats = ExtensionStats::default();
+        stats.add_children(node);
+        stats
+    }
+
+    fn add_children(&mut self, node: &TreeNode) {
+        for child in &node.children {
+            if child.kind == EntryKind::File {
+                let size = child.metadata.as_ref().map_or(0, |m| m.len());
+                let extension = child
+                    .path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                let category = extension_class(&child.path).map_or("other", FileClass::css_class);
+                for totals in [
+                    self.extensions.entry(extension).or_default(),
+                    self.categories.entry(category).or_default(),
+                    &mut self.total,
+                ] {
+                    totals.0 += 1;
+                    totals.1 += size;
                 }
-                child.flatten_inner(&child_prefix, out, i == n - 1);
             }
+            self.add_children(child);
         }
     }
+
+    /// Rows of a table, largest total size first
+    fn sorted<'a>(
+        table: impl Iterator<Item = (&'a str, &'a (usize, u64))>,
+    ) -> Vec<(&'a str, usize, u64)> {
+        let mut rows: Vec<_> = table
+            .map(|(key, &(files, bytes))| (key, files, bytes))
+            .collect();
+        rows.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(b.0)));
+        rows
+    }
+
+    fn extension_rows(&self) -> Vec<(&str, usize, u64)> {
+        Self::sorted(
+            self.extensions
+                .iter()
+                .map(|(key, totals)| (key.as_str(), totals)),
+        )
+    }
+
+    fn category_rows(&self) -> Vec<(&str, usize, u64)> {
+        Self::sorted(self.categories.iter().map(|(key, totals)| (*key, totals)))
+    }
 }
 
-/// Recursively displays a directory tree structure.
-///
-/// # Arguments
-///
-/// * `path` - The path to display
-/// * `show_hidden` - Whether to show hidden files (starting with '.')
-/// * `max_depth` - Maximum depth to traverse (None for unlimited)
-/// * `prefix` - The prefix string for tree formatting
-/// * `current_depth` - The current depth in the traversal
-///
-/// # Returns
-///
-/// Returns `Ok(())` on success, or an `std::io::Error` if directory reading fails.
-///
-/// # Examples
-///
-/// ```ignore
-/// use std::path::Path;
-/// let path = Path::new(".");
-/// display_tree(&path, false, Some(2), "", 0).unwrap();
-/// ```
-fn display_tree(
-    path: &Path,
-    show_hidden: bool,
-    max_depth: Option<usize>,
-    prefix: &str,
-    current_depth: usize,
-) -> std::io::Result<()> {
-    // Check if we've reached max depth
-    #[allow(clippy::collapsible_if)]
-    if let Some(max) = max_depth {
-        if current_depth > max {
-            return Ok(());
+/// Renders `--stats` in the selected output format: JSON, CSV, TSV, or
+/// aligned text tables for the default tree format
+fn render_stats(stats: &ExtensionStats, output: &str) -> String {
+    match output {
+        "json" => render_stats_json(stats),
+        "csv" => render_stats_table(stats, ','),
+        "tsv" => render_stats_table(stats, '\t'),
+        _ => render_stats_text(stats),
+    }
+}
+
+fn render_stats_text(stats: &ExtensionStats) -> String {
+    let mut out = String::new();
+    let mut section = |title: &str, rows: Vec<(&str, usize, u64)>| {
+        out.push_str(&format!("{:<16} {:>8} {:>12}\n", title, "Files", "Size"));
+        for (key, files, bytes) in rows {
+            let key = if key.is_empty() { "(none)" } else { key };
+            out.push_str(&format!(
+                "{:<16} {:>8} {:>12}\n",
+                key,
+                files,
+                format_total_size(bytes)
+            ));
+        }
+        out.push('\n');
+    };
+    section("Extension", stats.extension_rows());
+    section("Category", stats.category_rows());
+    let (files, bytes) = stats.total;
+    out.push_str(&format!(
+        "{:<16} {:>8} {:>12}\n",
+        "Total",
+        files,
+        format_total_size(bytes)
+    ));
+    out
+}
+
+fn render_stats_table(stats: &ExtensionStats, separator: char) -> String {
+    let mut out = ["group", "key", "files", "bytes"].join(&separator.to_string());
+    out.push('\n');
+    let groups = [
+        ("extension", stats.extension_rows()),
+        ("category", stats.category_rows()),
+        ("total", vec![("", stats.total.0, stats.total.1)]),
+    ];
+    for (group, rows) in groups {
+        for (key, files, bytes) in rows {
+            let row = [
+                group.to_string(),
+                key.to_string(),
+                files.to_string(),
+                bytes.to_string(),
+            ];
+            let row: Vec<String> = row
+                .iter()
+                .map(|field| table_field(field, separator))
+                .collect();
+            out.push_str(&row.join(&separator.to_string()));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn render_stats_json(stats: &ExtensionStats) -> String {
+    let rows = |key_name: &str, rows: Vec<(&str, usize, u64)>| {
+        let items: Vec<String> = rows
+            .iter()
+            .map(|(key, files, bytes)| {
+                format!(
+                    "    {{\"{}\": {}, \"files\": {}, \"bytes\": {}}}",
+                    key_name,
+                    json_string(key),
+                    files,
+                    bytes
+                )
+            })
+            .collect();
+        if items.is_empty() {
+            "[]".to_string()
+        } else {
+            format!("[\n{}\n  ]", items.join(",\n"))
+        }
+    };
+    format!(
+        "{{\n  \"extensions\": {},\n  \"categories\": {},\n  \"total\": {{\"files\": {}, \"bytes\": {}}}\n}}\n",
+        rows("extension", stats.extension_rows()),
+        rows("category", stats.category_rows()),
+        stats.total.0,
+        stats.total.1
+    )
+}
+
+/// Metadata columns shown in brackets before the entry names of the text
+/// tree and the interactive list, like GNU tree's `[ 4096]  name`
+#[derive(Debug, Clone, Default)]
+struct Columns {
+    long: Vec<LongColumn>,
+    /// User and group names by id, read once for the owner and group columns
+    user_names: HashMap<u32, String>,
+    group_names: HashMap<u32, String>,
+    size: Option<SizeFormat>,
+    /// Show cumulative directory sizes, computed with [`TreeNode::compute_du`]
+    du: Option<DuMode>,
+}
+
+impl Columns {
+    fn from_cli(cli: &Cli) -> Self {
+        let size = if cli.si {
+            Some(SizeFormat::Decimal)
+        } else if cli.human {
+            Some(SizeFormat::Binary)
+        } else if cli.size || cli.du.is_some() {
+            Some(SizeFormat::Bytes)
+        } else {
+            None
+        };
+        let long = if !cli.columns.is_empty() {
+            cli.columns.clone()
+        } else if cli.long {
+            DEFAULT_LONG_COLUMNS.to_vec()
+        } else {
+            vec![]
+        };
+        let user_names = if long.contains(&LongColumn::Owner) {
+            read_id_names("/etc/passwd")
+        } else {
+            HashMap::new()
+        };
+        let group_names = if long.contains(&LongColumn::Group) {
+            read_id_names("/etc/group")
+        } else {
+            HashMap::new()
+        };
+        Columns {
+            long,
+            user_names,
+            group_names,
+            size,
+            du: cli.du,
+        }
+    }
+
+    /// Formats a long listing column; `-` when the platform doesn't provide it
+    fn long_field(&self, column: LongColumn, node: &TreeNode, metadata: &fs::Metadata) -> String {
+        let owner = unix_owner_and_mode(metadata);
+        let name = |names: &HashMap<u32, String>, id: u32| {
+            names.get(&id).cloned().unwrap_or_else(|| id.to_string())
+        };
+        match column {
+            LongColumn::Mode => match owner {
+                Some((_, _, mode)) => mode_string(node.kind, mode),
+                None => format!("{:<10}", "-"),
+            },
+            LongColumn::Owner => {
+                let user = owner.map_or("-".to_string(), |(uid, _, _)| name(&self.user_names, uid));
+                format!("{:<8}", user)
+            }
+            LongColumn::Group => {
+                let group =
+                    owner.map_or("-".to_string(), |(_, gid, _)| name(&self.group_names, gid));
+                format!("{:<8}", group)
+            }
+            LongColumn::Mtime => metadata
+                .modified()
+                .ok()
+                .map_or(format!("{:<16}", "-"), |time| {
+                    // YYYY-MM-DDTHH:MM:SSZ -> YYYY-MM-DD HH:MM
+                    format_timestamp(time)[..16].replace('T', " ")
+                }),
+            LongColumn::Inode => {
+                let inode = unix_inode_and_links(metadata)
+                    .map_or("-".to_string(), |(ino, _)| ino.to_string());
+                format!("{:>9}", inode)
+            }
+            LongColumn::Links => {
+                let links =
+                    unix_inode_and_links(metadata).map_or("-".to_string(), |(_, n)| n.to_string());
+                format!("{:>3}", links)
+            }
+        }
+    }
+
+    /// The bracketed columns for `node` followed by two spaces, or nothing
+    /// when no column is enabled or the entry has no metadata
+    fn prefix(&self, node: &TreeNode) -> String {
+        let Some(metadata) = &node.metadata else {
+            return String::new();
+        };
+        let mut fields: Vec<String> = self
+            .long
+            .iter()
+            .map(|&column| self.long_field(column, node, metadata))
+            .collect();
+        if let Some(format) = self.size {
+            fields.push(format.format(self.size_of(node, metadata)));
+        }
+        if fields.is_empty() {
+            String::new()
+        } else {
+            format!("[{}]  ", fields.join(" "))
+        }
+    }
+
+    /// The size shown for `node`: the cumulative size of directories with `--du`
+    fn size_of(&self, node: &TreeNode, metadata: &fs::Metadata) -> u64 {
+        match (node.du_total, self.du) {
+            (Some(total), _) => total,
+            (None, Some(mode)) => mode.size_of(metadata),
+            (None, None) => metadata.len(),
+        }
+    }
+
+    /// The enabled columns of `node` as field names and unpadded values for
+    /// the structured output formats, leaving out the values the platform
+    /// doesn't provide
+    fn fields(&self, node: &TreeNode) -> Vec<(&'static str, String)> {
+        let Some(metadata) = &node.metadata else {
+            return vec![];
+        };
+        let mut fields = vec![];
+        for &column in &self.long {
+            let value = match column {
+                LongColumn::Mtime => metadata
+                    .modified()
+                    .map_or("-".to_string(), format_timestamp),
+                _ => self.long_field(column, node, metadata).trim().to_string(),
+            };
+            if value != "-" {
+                fields.push((column.as_str(), value));
+            }
+        }
+        if self.size.is_some() {
+            fields.push(("size", self.size_of(node, metadata).to_string()));
+        }
+        fields
+    }
+}
+
+/// Glyphs used to draw the branches of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Charset {
+    /// Connector for an entry that has siblings after it
+    branch: String,
+    /// Connector for the last entry of a directory
+    last: String,
+    /// Continuation line below an entry that has siblings after it
+    vertical: String,
+    /// Indentation below the last entry of a directory
+    space: String,
+}
+
+impl Charset {
+    /// Build a charset from its three visible glyphs.
+    ///
+    /// Shorter glyphs are padded with spaces so every glyph has the same
+    /// width, and the indentation glyph is made of that many spaces.
+    fn new(branch: &str, last: &str, vertical: &str) -> Self {
+        let width = [branch, last, vertical]
+            .iter()
+            .map(|g| g.chars().count())
+            .max()
+            .unwrap_or(0);
+        let pad = |glyph: &str| format!("{:<width$}", glyph, width = width);
+        Charset {
+            branch: pad(branch),
+            last: pad(last),
+            vertical: pad(vertical),
+            space: " ".repeat(width),
+        }
+    }
+
+    fn utf8() -> Self {
+        Charset::new("├── ", "└── ", "│   ")
+    }
+
+    fn ascii() -> Self {
+        Charset::new("|-- ", "`-- ", "|   ")
+    }
+
+    fn heavy() -> Self {
+        Charset::new("┣━━ ", "┗━━ ", "┃   ")
+    }
+
+    fn rounded() -> Self {
+        Charset::new("├── ", "╰── ", "│   ")
+    }
+}
+
+/// Parses the charset argument value
+fn parse_charset(s: &str) -> Result<Charset, String> {
+    match s {
+        "utf8" | "utf-8" => Ok(Charset::utf8()),
+        "ascii" => Ok(Charset::ascii()),
+        "heavy" => Ok(Charset::heavy()),
+        "rounded" => Ok(Charset::rounded()),
+        _ => {
+            let glyphs: Vec<&str> = s
+                .strip_prefix("custom:")
+                .map(|spec| spec.split(',').collect())
+                .unwrap_or_default();
+            match glyphs.as_slice() {
+                [branch, last, vertical] => Ok(Charset::new(branch, last, vertical)),
+                _ => Err(format!(
+                    "invalid charset '{}', must be one of: utf8, ascii, heavy, rounded, custom:BRANCH,LAST,VERTICAL",
+                    s
+                )),
+            }
+        }
+    }
+}
+
+/// Supported values of the `--output` option
+const OUTPUT_FORMATS: &[&str] = &[
+    "tree",
+    "json",
+    "ndjson",
+    "xml",
+    "html",
+    "markdown",
+    "dot",
+    "mermaid",
+    "mermaid-mindmap",
+    "plantuml",
+    "csv",
+    "tsv",
+];
+
+/// Values of the `--output` option supported by `--stats`
+const STATS_OUTPUT_FORMATS: &[&str] = &["tree", "json", "csv", "tsv"];
+
+impl Cli {
+    /// Checks the combinations of options that clap can't express declaratively
+    fn validate(&self) -> Result<(), clap::Error> {
+        if self.stats && !STATS_OUTPUT_FORMATS.contains(&self.output.as_str()) {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "the argument '--stats' cannot be used with '--output {}', \
+                     use one of: {}",
+                    self.output,
+                    STATS_OUTPUT_FORMATS.join(", ")
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Validates the output format argument value
+fn validate_output(s: &str) -> Result<String, String> {
+    if OUTPUT_FORMATS.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "invalid output value '{}', must be one of: {}",
+            s,
+            OUTPUT_FORMATS.join(", ")
+        ))
+    }
+}
+
+/// Compiles a regular expression argument
+fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+/// Validates the --regex-on argument
+fn validate_regex_on(s: &str) -> Result<String, String> {
+    match s {
+        "name" | "path" => Ok(s.to_string()),
+        _ => Err(format!(
+            "invalid regex target '{}', must be one of: name, path",
+            s
+        )),
+    }
+}
+
+/// Parses a --type argument, given as a find-style letter or a type name
+fn parse_entry_kind(s: &str) -> Result<EntryKind, String> {
+    match s {
+        "d" | "directory" => Ok(EntryKind::Directory),
+        "f" | "file" => Ok(EntryKind::File),
+        "l" | "symlink" => Ok(EntryKind::Symlink),
+        "s" | "socket" => Ok(EntryKind::Socket),
+        "p" | "fifo" => Ok(EntryKind::Fifo),
+        "b" | "block_device" => Ok(EntryKind::BlockDevice),
+        "c" | "char_device" => Ok(EntryKind::CharDevice),
+        _ => Err(format!(
+            "invalid type '{}', must be one of: d, f, l, s, p, b, c",
+            s
+        )),
+    }
+}
+
+/// Parses a size such as `512`, `10M`, `1.5GiB` or `2GB` into bytes.
+///
+/// Like GNU tools, single-letter and `iB` suffixes are powers of 1024 and
+/// `B` suffixes (`KB`, `MB`, ...) powers of 1000.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let err = || {
+        format!(
+            "invalid size '{}', expected a number with an optional unit (K, M, G, T, KiB, KB, ...)",
+            s
+        )
+    };
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| err())?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let (prefix, base) = match unit.as_str() {
+        "" | "B" => ("", 1024.0),
+        _ if unit.ends_with("IB") => (&unit[..unit.len() - 2], 1024.0),
+        _ if unit.len() == 2 && unit.ends_with('B') => (&unit[..1], 1000.0),
+        _ => (unit.as_str(), 1024.0),
+    };
+    let exponent = match prefix {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => return Err(err()),
+    };
+    Ok((number * f64::powi(base, exponent)).round() as u64)
+}
+
+/// Parses a --columns entry
+fn parse_long_column(s: &str) -> Result<LongColumn, String> {
+    match s {
+        "mode" => Ok(LongColumn::Mode),
+        "owner" => Ok(LongColumn::Owner),
+        "group" => Ok(LongColumn::Group),
+        "mtime" => Ok(LongColumn::Mtime),
+        "inode" => Ok(LongColumn::Inode),
+        "links" => Ok(LongColumn::Links),
+        _ => Err(format!(
+            "invalid column '{}', must be one of: mode, owner, group, mtime, inode, links",
+            s
+        )),
+    }
+}
+
+/// Parses the --du argument
+fn parse_du_mode(s: &str) -> Result<DuMode, String> {
+    match s {
+        "apparent" => Ok(DuMode::Apparent),
+        "allocated" => Ok(DuMode::Allocated),
+        _ => Err(format!(
+            "invalid disk usage mode '{}', must be one of: apparent, allocated",
+            s
+        )),
+    }
+}
+
+/// Parses a --perm argument: an octal mode, optionally prefixed with `-`
+/// or `/`, or a keyword for one of the special bits
+fn parse_perm(s: &str) -> Result<PermMatch, String> {
+    match s {
+        "setuid" => return Ok(PermMatch::Any(MODE_SETUID)),
+        "setgid" => return Ok(PermMatch::Any(MODE_SETGID)),
+        "sticky" => return Ok(PermMatch::Any(MODE_STICKY_BIT)),
+        "executable" => return Ok(PermMatch::Any(MODE_EXECUTABLE)),
+        "world-writable" => return Ok(PermMatch::Any(MODE_WORLD_WRITABLE)),
+        _ => {}
+    }
+    let octal = s.trim_start_matches(['-', '/']);
+    let bits = u32::from_str_radix(octal, 8)
+        .ok()
+        .filter(|bits| *bits <= 0o7777 && s.len() - octal.len() <= 1)
+        .ok_or_else(|| {
+            format!(
+                "invalid mode '{}', expected an octal mode (644, -4000, /022) or one of: \
+                 setuid, setgid, sticky, executable, world-writable",
+                s
+            )
+        })?;
+    Ok(match s.chars().next() {
+        Some('-') => PermMatch::All(bits),
+        Some('/') => PermMatch::Any(bits),
+        _ => PermMatch::Exact(bits),
+    })
+}
+
+/// Parses a --user argument, a uid or a name from /etc/passwd
+fn parse_user(s: &str) -> Result<u32, String> {
+    s.parse()
+        .ok()
+        .or_else(|| lookup_id("/etc/passwd", s))
+        .ok_or_else(|| format!("unknown user '{}'", s))
+}
+
+/// Parses a --group argument, a gid or a name from /etc/group
+fn parse_group(s: &str) -> Result<u32, String> {
+    s.parse()
+        .ok()
+        .or_else(|| lookup_id("/etc/group", s))
+        .ok_or_else(|| format!("unknown group '{}'", s))
+}
+
+/// Looks up the id of `name` in a `name:password:id:...` database like /etc/passwd
+fn lookup_id(database: &str, name: &str) -> Option<u32> {
+    read_id_database(database)
+        .into_iter()
+        .find_map(|(entry, id)| (entry == name).then_some(id))
+}
+
+/// Parses the --time-field argument
+fn parse_time_field(s: &str) -> Result<TimeField, String> {
+    match s {
+        "mtime" => Ok(TimeField::Modified),
+        "ctime" => Ok(TimeField::Changed),
+        "atime" => Ok(TimeField::Accessed),
+        _ => Err(format!(
+            "invalid time field '{}', must be one of: mtime, ctime, atime",
+            s
+        )),
+    }
+}
+
+/// Parses a --newer-than/--older-than argument: a duration before now, a
+/// UTC date and time, or a reference file whose mtime is used
+fn parse_time_spec(s: &str) -> Result<SystemTime, String> {
+    if let Some(duration) = parse_duration(s) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| format!("duration '{}' is too large", s));
+    }
+    if let Some(time) = parse_datetime(s) {
+        return Ok(time);
+    }
+    fs::metadata(s).and_then(|m| m.modified()).map_err(|_| {
+        format!(
+            "invalid time '{}', expected a duration (30m, 3d, 2w), a date (2024-05-01, \
+             2024-05-01T12:00:00) or an existing reference file",
+            s
+        )
+    })
+}
+
+/// Parses a duration made of `<number><unit>` parts such as `90s`, `3d` or
+/// `1h30m`, with units s, m, h, d and w
+fn parse_duration(s: &str) -> Option<Duration> {
+    let mut secs: u64 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit_len = rest[digits..]
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len() - digits);
+        let value: u64 = rest[..digits].parse().ok()?;
+        let unit = match &rest[digits..digits + unit_len] {
+            "s" => 1,
+            "m" | "min" => 60,
+            "h" => 3600,
+            "d" => 86_400,
+            "w" => 7 * 86_400,
+            _ => return None,
+        };
+        secs = secs.checked_add(value.checked_mul(unit)?)?;
+        rest = &rest[digits + unit_len..];
+    }
+    (!s.is_empty()).then(|| Duration::from_secs(secs))
+}
+
+/// Parses a UTC `YYYY-MM-DD` date, optionally followed by `T` or a space and
+/// `HH:MM` or `HH:MM:SS`, and an optional trailing `Z`
+fn parse_datetime(s: &str) -> Option<SystemTime> {
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let date: Vec<&str> = date.split('-').collect();
+    let [year, month, day] = date[..] else {
+        return None;
+    };
+    let (year, month, day): (i64, u32, u32) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    // Keeps the day arithmetic below far from overflowing
+    if year > 9999 || !(1..=12).contains(&month) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // Reject dates like 2024-02-30 that don't survive the round trip
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    let mut secs = days * 86_400;
+    if let Some(time) = time {
+        let parts: Vec<u32> = time
+            .split(':')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        let (hour, minute, second) = match parts[..] {
+            [hour, minute] => (hour, minute, 0),
+            [hour, minute, second] => (hour, minute, second),
+            _ => return None,
+        };
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        secs += i64::from(hour * 3600 + minute * 60 + second);
+    }
+    let since_epoch = Duration::from_secs(secs.unsigned_abs());
+    if secs >= 0 {
+        UNIX_EPOCH.checked_add(since_epoch)
+    } else {
+        UNIX_EPOCH.checked_sub(since_epoch)
+    }
+}
+
+/// Validates the markdown style argument value
+fn validate_markdown_style(s: &str) -> Result<String, String> {
+    match s {
+        "list" | "fenced" => Ok(s.to_string()),
+        _ => Err(format!(
+            "invalid markdown style '{}', must be one of: list, fenced",
+            s
+        )),
+    }
+}
+
+/// Main entry point for the rutree2 application.
+///
+/// Parses command-line arguments and initiates the directory tree display.
+fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = cli.validate() {
+        e.exit();
+    }
+
+    // Configure colored output based on --color option
+    match cli.color.as_str() {
+        "never" => colored::control::set_override(false),
+        "always" => colored::control::set_override(true),
+        _ => {
+            // Auto-detect: use colors if stdout is a terminal
+            if !std::io::stdout().is_terminal() {
+                colored::control::set_override(false);
+            }
+        }
+    }
+
+    if cli.fromfile {
+        display_listing(&cli);
+        return;
+    }
+
+    // Validate the path exists
+    if !cli.path.exists() {
+        eprintln!("Error: Path '{}' does not exist", cli.path.display());
+        std::process::exit(1);
+    }
+
+    let walk = WalkOptions::from_cli(&cli);
+    let columns = Columns::from_cli(&cli);
+    if cli.stats {
+        let result = TreeNode::from_path(&cli.path, &walk, 0).and_then(|tree| {
+            let report = render_stats(&ExtensionStats::of(&tree), &cli.output);
+            std::io::stdout().lock().write_all(report.as_bytes())
+        });
+        if let Err(e) = result
+            && e.kind() != std::io::ErrorKind::BrokenPipe
+        {
+            eprintln!("Error reading directory '{}': {}", cli.path.display(), e);
+            std::process::exit(1);
+        }
+    } else if cli.interactive {
+        let result = TreeNode::from_path(&cli.path, &walk, 0).and_then(|mut tree| {
+            if let Some(mode) = columns.du {
+                tree.compute_du(&walk, mode);
+            }
+            interactive_tree(tree, &cli.charset, &columns)
+        });
+        if let Err(e) = result {
+            eprintln!("Interactive mode error: {}", e);
+            std::process::exit(1);
+        }
+    } else {
+        let result = if cli.output == "tree" {
+            display_tree(&cli.path, &walk, &cli.charset, &columns).and_then(|stats| {
+                if cli.noreport {
+                    Ok(())
+                } else {
+                    writeln!(std::io::stdout(), "\n{}", stats)
+                }
+            })
+        } else if cli.output == "ndjson" && !walk.prune && columns.du.is_none() {
+            // Streaming can't know in advance whether a directory ends up empty
+            // or how large it is, so --prune and --du fall back to building
+            // the tree first
+            let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+            stream_ndjson(&cli.path, &walk, &columns, &mut out).and_then(|_| out.flush())
+        } else {
+            TreeNode::from_path(&cli.path, &walk, 0).and_then(|mut tree| {
+                if let Some(mode) = columns.du {
+                    tree.compute_du(&walk, mode);
+                }
+                let rendered = render_output(&cli, &tree, &columns);
+                std::io::stdout().lock().write_all(rendered.as_bytes())
+            })
+        };
+        // A closed pipe (e.g. `| head`) just means the reader has seen enough
+        if let Err(e) = result
+            && e.kind() != std::io::ErrorKind::BrokenPipe
+        {
+            eprintln!("Error reading directory '{}': {}", cli.path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Splits a listed path into the names leading to it from the root, resolving
+/// `.` and `..` lexically. Absolute paths are placed below the root like
+/// relative ones, and paths climbing above the root give `None`.
+fn listing_components(line: &str) -> Option<Vec<String>> {
+    let mut components = vec![];
+    for component in Path::new(line).components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                components.pop()?;
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(components)
+}
+
+/// Displays a tree built from a list of paths (`--fromfile`) in the selected
+/// output format, without touching the filesystem.
+fn display_listing(cli: &Cli) {
+    let listing = match read_listing(&cli.path, cli.null) {
+        Ok(listing) => listing,
+        Err(e) => {
+            eprintln!("Error reading path list '{}': {}", cli.path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let root_name = cli.path.to_string_lossy();
+    let tree = TreeNode::from_listing(&root_name, &listing, &WalkOptions::from_cli(cli));
+
+    if cli.interactive {
+        if let Err(e) = interactive_tree(tree, &cli.charset, &Columns::from_cli(cli)) {
+            eprintln!("Interactive mode error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let rendered = if cli.stats {
+        render_stats(&ExtensionStats::of(&tree), &cli.output)
+    } else if cli.output == "tree" {
+        let mut out = render_text(&tree, &cli.charset);
+        if !cli.noreport {
+            out.push_str(&format!("\n{}\n", TreeStats::of(&tree)));
+        }
+        out
+    } else {
+        render_output(cli, &tree, &Columns::default())
+    };
+    // A closed pipe (e.g. `| head`) just means the reader has seen enough
+    if let Err(e) = std::io::stdout().lock().write_all(rendered.as_bytes())
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        eprintln!("Error writing output: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Reads a list of paths, one per line or NUL-separated, from a file or
+/// from stdin when `source` is `.` or `-`. Empty entries are skipped.
+fn read_listing(source: &Path, null_separated: bool) -> std::io::Result<Vec<String>> {
+    let mut bytes = Vec::new();
+    if source == Path::new(".") || source == Path::new("-") {
+        std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut bytes)?;
+    } else {
+        bytes = fs::read(source)?;
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    let entries: Vec<String> = if null_separated {
+        text.split('\0').map(str::to_string).collect()
+    } else {
+        text.lines().map(str::to_string).collect()
+    };
+    Ok(entries.into_iter().filter(|e| !e.is_empty()).collect())
+}
+
+/// Interactive collapsible/expandable tree using ratatui
+fn interactive_tree(
+    mut tree: TreeNode,
+    charset: &Charset,
+    columns: &Columns,
+) -> std::io::Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = std::io::stdout();
+    crossterm::execute!(stdout, crossterm::terminal::EnterAlternateScreen)?;
+    let backend = ratatui::backend::CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // Legend overlay content and size
+    let legend_lines = [
+        "↑/↓: Move",
+        "→/Enter: Expand/Enter Dir",
+        "←: Collapse",
+        "q: Quit",
+    ];
+    let legend_width = legend_lines.iter().map(|l| l.len()).max().unwrap_or(12) as u16 + 4;
+    let legend_height = legend_lines.len() as u16 + 2;
+
+    // Build initial tree state
+    let mut state = ListState::default();
+    state.select(Some(0));
+
+    let mut flat = tree.flatten(charset);
+
+    loop {
+        terminal.draw(|f| {
+            let size = f.area();
+            let legend_x = size.x + size.width.saturating_sub(legend_width);
+            let legend_y = size.y;
+            let legend_area = ratatui::layout::Rect {
+                x: legend_x,
+                y: legend_y,
+                width: legend_width,
+                height: legend_height,
+            };
+
+            let selected_idx = state.selected().unwrap_or(0);
+            let items: Vec<ListItem> = flat
+                .iter()
+                .enumerate()
+                .map(|(i, (prefix, node))| {
+                    let mut label =
+                        format!("{}{}{}", prefix, columns.prefix(node), node.display_name());
+                    if node.is_dir && !node.expanded {
+                        label.push_str(" [+]");
+                    } else if node.is_dir && node.expanded {
+                        label.push_str(" [-]");
+                    }
+                    if i == selected_idx {
+                        ListItem::new(label).style(
+                            ratatui::style::Style::default()
+                                .fg(ratatui::style::Color::Black)
+                                .bg(ratatui::style::Color::Yellow)
+                                .add_modifier(ratatui::style::Modifier::BOLD),
+                        )
+                    } else {
+                        ListItem::new(label)
+                    }
+                })
+                .collect();
+            let list = List::new(items).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("rutree2 (interactive)"),
+            );
+            f.render_stateful_widget(list, size, &mut state);
+            let legend_items: Vec<ListItem> =
+                legend_lines.iter().map(|l| ListItem::new(*l)).collect();
+            let legend = List::new(legend_items)
+                .block(Block::default().borders(Borders::ALL).title("Legend"));
+            f.render_widget(legend, legend_area);
+        })?;
+
+        if let Event::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Char('q') => break,
+                KeyCode::Down => {
+                    let sel = state.selected().unwrap_or(0);
+                    let new_sel = if sel + 1 < flat.len() { sel + 1 } else { sel };
+                    state.select(Some(new_sel));
+                }
+                KeyCode::Up => {
+                    let sel = state.selected().unwrap_or(0);
+                    let new_sel = if sel > 0 { sel - 1 } else { 0 };
+                    state.select(Some(new_sel));
+                }
+                KeyCode::Right | KeyCode::Enter => {
+                    let sel = state.selected().unwrap_or(0);
+                    if let Some(node) = get_mut_node(&mut tree, sel)
+                        && node.is_dir
+                    {
+                        node.expanded = !node.expanded;
+                        flat = tree.flatten(charset);
+                        let new_sel = sel.min(flat.len().saturating_sub(1));
+                        state.select(Some(new_sel));
+                    }
+                }
+                KeyCode::Left => {
+                    let sel = state.selected().unwrap_or(0);
+                    if let Some(node) = get_mut_node(&mut tree, sel)
+                        && node.is_dir
+                        && node.expanded
+                    {
+                        node.expanded = false;
+                        flat = tree.flatten(charset);
+                        let new_sel = sel.min(flat.len().saturating_sub(1));
+                        state.select(Some(new_sel));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    disable_raw_mode()?;
+    crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen)?;
+    Ok(())
+}
+
+/// Kind of a filesystem entry, determined without following symlinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Socket,
+    Fifo,
+}
+
+impl EntryKind {
+    /// Detect the kind from `symlink_metadata`, falling back to `File` if it could not be read
+    fn of(metadata: Option<&fs::Metadata>) -> Self {
+        metadata.map_or(EntryKind::File, |m| Self::from_file_type(m.file_type()))
+    }
+
+    fn from_file_type(file_type: fs::FileType) -> Self {
+        if file_type.is_symlink() {
+            return EntryKind::Symlink;
+        }
+        if file_type.is_dir() {
+            return EntryKind::Directory;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if file_type.is_char_device() {
+                return EntryKind::CharDevice;
+            }
+            if file_type.is_block_device() {
+                return EntryKind::BlockDevice;
+            }
+            if file_type.is_socket() {
+                return EntryKind::Socket;
+            }
+            if file_type.is_fifo() {
+                return EntryKind::Fifo;
+            }
+        }
+        EntryKind::File
+    }
+
+    /// Name used for the entry type in structured output formats
+    fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::Directory => "directory",
+            EntryKind::File => "file",
+            EntryKind::Symlink => "symlink",
+            EntryKind::CharDevice => "char_device",
+            EntryKind::BlockDevice => "block_device",
+            EntryKind::Socket => "socket",
+            EntryKind::Fifo => "fifo",
+        }
+    }
+}
+
+/// Marker shown for directories whose entries could not be read, as in GNU tree
+const READ_ERROR_LABEL: &str = "error opening dir";
+
+/// Tree node for interactive mode and structured output formats
+#[derive(Debug, Clone)]
+struct TreeNode {
+    name: String,
+    path: PathBuf,
+    kind: EntryKind,
+    link_target: Option<PathBuf>,
+    metadata: Option<fs::Metadata>,
+    is_dir: bool,
+    expanded: bool,
+    children: Vec<TreeNode>,
+    depth: usize,
+    /// Number of lines matching `--contains`, when shown with `--count`
+    matches: Option<usize>,
+    /// Number of children left out by `--filelimit`
+    elided: usize,
+    /// Cumulative size of a directory with `--du`
+    du_total: Option<u64>,
+    /// Whether the directory's entries could not be read
+    read_error: bool,
+}
+
+impl TreeNode {
+    fn from_path(path: &Path, walk: &WalkOptions, depth: usize) -> std::io::Result<Self> {
+        let mut node = TreeNode::new(path, depth);
+        node.matches = walk.match_count(&node);
+        if node.is_dir && walk.descends(depth) {
+            let mut entries = match read_entries(path, walk) {
+                Ok(entries) => entries,
+                Err(err) if depth == 0 => return Err(err),
+                Err(_) => {
+                    node.read_error = true;
+                    return Ok(node);
+                }
+            };
+            // `--filelimit` applies to the entries that survive `--prune`.
+            // Without it, every entry is listed, so the ones past the limit
+            // are left out before being read.
+            if !walk.prune {
+                let elided = walk.limit_entries(&mut entries);
+                walk.forget_matches(&elided);
+                node.elided = elided.len();
+            }
+            for entry in entries {
+                let child = TreeNode::from_path(&entry.path(), walk, depth + 1)?;
+                if !walk.prunes(&child) {
+                    node.children.push(child);
+                }
+            }
+            if walk.prune {
+                node.elided = walk.limit_entries(&mut node.children).len();
+            }
+        }
+        Ok(node)
+    }
+
+    /// Create a node for `path` without reading its children
+    fn new(path: &Path, depth: usize) -> Self {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(".")
+            .to_string();
+        let metadata = path.symlink_metadata().ok();
+        let kind = EntryKind::of(metadata.as_ref());
+        let link_target = if kind == EntryKind::Symlink {
+            fs::read_link(path).ok()
+        } else {
+            None
+        };
+        let is_dir = path.is_dir();
+        TreeNode {
+            name,
+            path: path.to_path_buf(),
+            kind,
+            link_target,
+            metadata,
+            is_dir,
+            expanded: depth == 0, // root expanded
+            children: vec![],
+            depth,
+            matches: None,
+            elided: 0,
+            du_total: None,
+            read_error: false,
+        }
+    }
+
+    /// Build a tree from a list of relative paths without touching the filesystem.
+    ///
+    /// Intermediate components become directories, as do paths ending in `/`.
+    /// Paths with a component filtered out by `walk`, and components deeper
+    /// than its maximum depth, are skipped like they are when walking a real
+    /// directory.
+    fn from_listing(root_name: &str, paths: &[String], walk: &WalkOptions) -> Self {
+        let mut root = TreeNode::virtual_node(root_name, PathBuf::new(), true, 0);
+        for line in paths {
+            let Some(components) = listing_components(line) else {
+                continue;
+            };
+            let depth_limit = walk.max_depth.unwrap_or(usize::MAX).min(components.len());
+            let last_is_dir = line.ends_with('/') || depth_limit < components.len();
+            let filtered_out = (1..=depth_limit).any(|len| {
+                let is_dir = len < depth_limit || last_is_dir;
+                !walk.keeps(&components[..len].join("/"), is_dir, None)
+            });
+            if filtered_out {
+                continue;
+            }
+            root.insert_listing_path(&components[..depth_limit], last_is_dir);
+        }
+        root.sort_children();
+        root.prune_empty_dirs(walk);
+        root.limit_children(walk);
+        root
+    }
+
+    /// Create a node that does not correspond to an entry on disk
+    fn virtual_node(name: &str, path: PathBuf, is_dir: bool, depth: usize) -> Self {
+        TreeNode {
+            name: name.to_string(),
+            path,
+            kind: if is_dir {
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            },
+            link_target: None,
+            metadata: None,
+            is_dir,
+            expanded: depth == 0,
+            children: vec![],
+            depth,
+            matches: None,
+            elided: 0,
+            du_total: None,
+            read_error: false,
+        }
+    }
+
+    fn insert_listing_path(&mut self, components: &[String], last_is_dir: bool) {
+        let Some((name, rest)) = components.split_first() else {
+            return;
+        };
+        let is_dir = !rest.is_empty() || last_is_dir;
+        // Listings are usually sorted, so the matching child is most often the last one
+        let index = match self.children.iter().rposition(|c| &c.name == name) {
+            Some(index) => index,
+            None => {
+                let path = self.path.join(name);
+                let child = TreeNode::virtual_node(name, path, is_dir, self.depth + 1);
+                self.children.push(child);
+                self.children.len() - 1
+            }
+        };
+        let child = &mut self.children[index];
+        if is_dir && !child.is_dir {
+            child.is_dir = true;
+            child.kind = EntryKind::Directory;
+        }
+        child.insert_listing_path(rest, last_is_dir);
+    }
+
+    /// Classifies the node for coloring; entries from a listing are only
+    /// classified by their kind and extension, without touching the disk
+    fn file_class(&self) -> FileClass {
+        if self.metadata.is_some() {
+            classify_file(&self.path)
+        } else if self.is_dir {
+            FileClass::Directory
+        } else {
+            extension_class(&self.path).unwrap_or(FileClass::Plain)
+        }
+    }
+
+    fn sort_children(&mut self) {
+        self.children.sort_by(|a, b| a.name.cmp(&b.name));
+        for child in &mut self.children {
+            child.sort_children();
+        }
+    }
+
+    /// Computes the cumulative size of every directory, bottom-up, and
+    /// returns the one of this node.
+    ///
+    /// Directories cut off by the depth limit are walked to include their
+    /// contents, but otherwise only the entries kept by the filters are
+    /// counted. Symbolic links count for their own size, without the
+    /// contents of the directories they point to.
+    fn compute_du(&mut self, walk: &WalkOptions, mode: DuMode) -> u64 {
+        let own = self.metadata.as_ref().map_or(0, |m| mode.size_of(m));
+        if !self.is_dir || self.kind == EntryKind::Symlink {
+            return own;
+        }
+        let below = if walk.descends(self.depth) {
+            let shown: u64 = self
+                .children
+                .iter_mut()
+                .map(|child| child.compute_du(walk, mode))
+                .sum();
+            shown + self.elided_disk_usage(walk, mode)
+        } else {
+            disk_usage_below(&self.path, walk, mode)
+        };
+        self.du_total = Some(own + below);
+        own + below
+    }
+
+    /// Size of the entries left out by `--filelimit`, which still count in
+    /// the total of their directory
+    fn elided_disk_usage(&self, walk: &WalkOptions, mode: DuMode) -> u64 {
+        if self.elided == 0 {
+            return 0;
+        }
+        let Ok(entries) = read_entries(&self.path, walk) else {
+            return 0;
+        };
+        walk.forget_matches(&entries);
+        entries
+            .iter()
+            .filter(|entry| !self.children.iter().any(|c| c.path == entry.path()))
+            .map(|entry| disk_usage(&entry.path(), walk, mode))
+            .sum()
+    }
+
+    /// Recursively applies `--filelimit` to an already built tree
+    fn limit_children(&mut self, walk: &WalkOptions) {
+        self.elided = walk.limit_entries(&mut self.children).len();
+        for child in &mut self.children {
+            child.limit_children(walk);
+        }
+    }
+
+    /// Recursively removes the descendants that `--prune` drops, see [`WalkOptions::prunes`]
+    fn prune_empty_dirs(&mut self, walk: &WalkOptions) {
+        for child in &mut self.children {
+            child.prune_empty_dirs(walk);
+        }
+        self.children.retain(|child| !walk.prunes(child));
+    }
+
+    fn display_name(&self) -> String {
+        let name = if self.is_dir {
+            format!("{}/", self.name)
+        } else {
+            self.name.clone()
+        };
+        let name = match (&self.link_target, self.kind) {
+            (Some(target), _) => format!("{} -> {}", name, target.display()),
+            (None, EntryKind::Symlink) => format!("{} -> [broken link]", name),
+            _ => name,
+        };
+        let name = match self.matches {
+            Some(1) => format!("{} (1 match)", name),
+            Some(count) => format!("{} ({} matches)", name, count),
+            None => name,
+        };
+        if self.read_error {
+            format!("{} [{}]", name, READ_ERROR_LABEL)
+        } else {
+            name
+        }
+    }
+
+    /// Flatten the tree for display, returning (prefix, &TreeNode)
+    fn flatten(&self, charset: &Charset) -> Vec<(String, TreeNode)> {
+        let mut out = vec![];
+        self.flatten_inner("", &mut out, true, charset);
+        out
+    }
+
+    fn flatten_inner(
+        &self,
+        prefix: &str,
+        out: &mut Vec<(String, TreeNode)>,
+        is_last: bool,
+        charset: &Charset,
+    ) {
+        let mut this_prefix = prefix.to_string();
+        if self.depth > 0 {
+            this_prefix += if is_last {
+                &charset.last
+            } else {
+                &charset.branch
+            };
+        }
+        out.push((this_prefix.clone(), self.clone()));
+        if self.is_dir && self.expanded {
+            let n = self.children.len();
+            for (i, child) in self.children.iter().enumerate() {
+                let mut child_prefix = prefix.to_string();
+                if self.depth > 0 {
+                    child_prefix += if is_last {
+                        &charset.space
+                    } else {
+                        &charset.vertical
+                    };
+                }
+                child.flatten_inner(&child_prefix, out, i == n - 1 && self.elided == 0, charset);
+            }
+            if self.elided > 0 {
+                let mut placeholder_prefix = prefix.to_string();
+                if self.depth > 0 {
+                    placeholder_prefix += if is_last {
+                        &charset.space
+                    } else {
+                        &charset.vertical
+                    };
+                }
+                placeholder_prefix += &charset.last;
+                let placeholder = TreeNode::virtual_node(
+                    &elided_label(self.elided),
+                    PathBuf::new(),
+                    false,
+                    self.depth + 1,
+                );
+                out.push((placeholder_prefix, placeholder));
+            }
+        }
+    }
+}
+
+/// Reads the entries of a directory, sorted by name.
+///
+/// This is the single place where entries are filtered (see
+/// [`WalkOptions::keeps`]), shared by [`TreeNode::from_path`] and
+/// [`stream_ndjson`].
+fn read_entries(path: &Path, walk: &WalkOptions) -> std::io::Result<Vec<fs::DirEntry>> {
+    let mut entries: Vec<_> = fs::read_dir(path)?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let entry_path = entry.path();
+            let rel_path = relative_path(&walk.root, &entry_path);
+            let metadata = entry_path.symlink_metadata().ok();
+            walk.keeps(&rel_path, entry_path.is_dir(), metadata.as_ref())
+        })
+        .collect();
+
+    // Sort entries by name
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+/// Displays a directory tree structure with colors.
+///
+/// The whole tree is built first so that filters like `--prune` can look at
+/// a directory's remaining children before it is printed.
+///
+/// # Arguments
+///
+/// * `path` - The path to display
+/// * `walk` - Which entries to show (hidden files, depth limit, patterns)
+/// * `charset` - The glyphs used to draw the tree branches
+/// * `columns` - The metadata columns shown before each name
+///
+/// # Returns
+///
+/// Returns the totals for the summary footer on success, or an
+/// `std::io::Error` if directory reading fails.
+///
+/// # Examples
+///
+/// ```ignore
+/// use std::path::Path;
+/// let path = Path::new(".");
+/// display_tree(&path, &WalkOptions::new(&path), &Charset::utf8(), &Columns::default()).unwrap();
+/// ```
+fn display_tree(
+    path: &Path,
+    walk: &WalkOptions,
+    charset: &Charset,
+    columns: &Columns,
+) -> std::io::Result<TreeStats> {
+    let mut tree = TreeNode::from_path(path, walk, 0)?;
+    if let Some(mode) = columns.du {
+        tree.compute_du(walk, mode);
+    }
+    let mut out = format!("{}\n", colorize_filename(&tree.name, &tree.path));
+    write_text_children(&tree, charset, columns, "", true, &mut out);
+    std::io::stdout().lock().write_all(out.as_bytes())?;
+    Ok(TreeStats::of(&tree))
+}
+
+/// Renders a tree in the structured output format selected on the command line
+fn render_output(cli: &Cli, tree: &TreeNode, columns: &Columns) -> String {
+    match cli.output.as_str() {
+        "xml" => render_xml(tree, columns),
+        "html" => render_html(tree),
+        "markdown" if cli.markdown_style == "fenced" => render_markdown_fenced(tree, &cli.charset),
+        "markdown" => render_markdown_list(tree),
+        "dot" => render_dot(tree),
+        "mermaid" => render_mermaid_graph(tree),
+        "mermaid-mindmap" => render_mermaid_mindmap(tree),
+        "plantuml" => render_plantuml(tree),
+        "csv" => render_table(tree, ','),
+        "tsv" => render_table(tree, '\t'),
+        "ndjson" => render_ndjson(tree, columns),
+        _ => render_json(tree, columns),
+    }
+}
+
+/// Renders a tree as pretty-printed JSON with one nested object per entry.
+///
+/// Each object carries the entry `name` and `type`, the `target` of symlinks,
+/// and a `children` array for directories.
+fn render_json(node: &TreeNode, columns: &Columns) -> String {
+    let mut out = String::new();
+    write_json_node(node, columns, 0, &mut out);
+    out.push('\n');
+    out
+}
+
+fn write_json_node(node: &TreeNode, columns: &Columns, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent);
+    out.push('{');
+    for (i, (key, value)) in json_entry_fields(node, columns).iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        out.push_str(&format!("{}\n{}  \"{}\": {}", separator, pad, key, value));
+    }
+    if node.is_dir {
+        out.push_str(&format!(",\n{}  \"children\": [", pad));
+        for (i, child) in node.children.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str(&format!("{}    ", pad));
+            write_json_node(child, columns, indent + 2, out);
+        }
+        if !node.children.is_empty() {
+            out.push_str(&format!("\n{}  ", pad));
+        }
+        out.push(']');
+    }
+    out.push_str(&format!("\n{}}}", pad));
+}
+
+/// Collects the JSON fields describing a single entry, shared by the nested
+/// JSON and NDJSON formats. Values are already encoded as JSON.
+fn json_entry_fields(node: &TreeNode, columns: &Columns) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("name", json_string(&node.name)),
+        ("type", json_string(node.kind.as_str())),
+    ];
+    if let Some(target) = &node.link_target {
+        fields.push(("target", json_string(&target.to_string_lossy())));
+    }
+    if let Some(count) = node.matches {
+        fields.push(("matches", count.to_string()));
+    }
+    if node.elided > 0 {
+        fields.push(("elided", node.elided.to_string()));
+    }
+    if node.read_error {
+        fields.push(("error", json_string(READ_ERROR_LABEL)));
+    }
+    for (key, value) in columns.fields(node) {
+        let value = match key {
+            "size" | "inode" | "links" => value,
+            _ => json_string(&value),
+        };
+        fields.push((key, value));
+    }
+    fields
+}
+
+/// Renders an already built tree as newline-delimited JSON, with the same
+/// records as [`stream_ndjson`].
+fn render_ndjson(node: &TreeNode, columns: &Columns) -> String {
+    let mut out = String::new();
+    write_ndjson_nodes(node, columns, ".", &mut out);
+    out
+}
+
+fn write_ndjson_nodes(node: &TreeNode, columns: &Columns, rel_path: &str, out: &mut String) {
+    out.push_str(&ndjson_record(node, columns, rel_path));
+    out.push('\n');
+    for child in &node.children {
+        let child_path = if node.depth == 0 {
+            child.name.clone()
+        } else {
+            format!("{}/{}", rel_path, child.name)
+        };
+        write_ndjson_nodes(child, columns, &child_path, out);
+    }
+}
+
+/// Formats the NDJSON record of a single entry, without the trailing newline
+fn ndjson_record(node: &TreeNode, columns: &Columns, rel_path: &str) -> String {
+    let mut record = format!(
+        "{{\"depth\":{},\"path\":{}",
+        node.depth,
+        json_string(rel_path)
+    );
+    for (key, value) in json_entry_fields(node, columns) {
+        record.push_str(&format!(",\"{}\":{}", key, value));
+    }
+    record.push('}');
+    record
+}
+
+/// Streams the walk as newline-delimited JSON.
+///
+/// One record is written per entry as soon as it is visited, with its
+/// `depth` and `path` relative to the root in addition to the fields of the
+/// nested JSON format. Only the entries of the directory being read are held
+/// in memory, so arbitrarily large trees can be piped into other tools.
+fn stream_ndjson(
+    path: &Path,
+    walk: &WalkOptions,
+    columns: &Columns,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    stream_ndjson_entry(path, ".", walk, columns, 0, out)
+}
+
+fn stream_ndjson_entry(
+    path: &Path,
+    rel_path: &str,
+    walk: &WalkOptions,
+    columns: &Columns,
+    depth: usize,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut node = TreeNode::new(path, depth);
+    node.matches = walk.match_count(&node);
+    let mut entries = vec![];
+    if node.is_dir && walk.descends(depth) {
+        match read_entries(path, walk) {
+            Ok(read) => entries = read,
+            Err(err) if depth == 0 => return Err(err),
+            Err(_) => node.read_error = true,
+        }
+        let elided = walk.limit_entries(&mut entries);
+        walk.forget_matches(&elided);
+        node.elided = elided.len();
+    }
+    writeln!(out, "{}", ndjson_record(&node, columns, rel_path))?;
+
+    for entry in entries {
+        let name = entry.file_name();
+        let child_path = if depth == 0 {
+            name.to_string_lossy().into_owned()
+        } else {
+            format!("{}/{}", rel_path, name.to_string_lossy())
+        };
+        stream_ndjson_entry(&entry.path(), &child_path, walk, columns, depth + 1, out)?;
+    }
+    Ok(())
+}
+
+/// Quotes and escapes a string as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Renders a tree as XML following the schema of GNU `tree -X`.
+///
+/// Entries are nested `<directory>`, `<file>`, `<link>` (etc.) elements
+/// inside `<tree>`, followed by a `<report>` with the counts of [`TreeStats`].
+fn render_xml(node: &TreeNode, columns: &Columns) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tree>\n");
+    write_xml_node(node, columns, 1, &mut out);
+    let stats = TreeStats::of(node);
+    out.push_str("  <report>\n");
+    out.push_str(&format!(
+        "    <directories>{}</directories>\n",
+        stats.directories
+    ));
+    out.push_str(&format!("    <files>{}</files>\n", stats.files));
+    out.push_str(&format!("    <symlinks>{}</symlinks>\n", stats.symlinks));
+    if stats.elided > 0 {
+        out.push_str(&format!("    <elided>{}</elided>\n", stats.elided));
+    }
+    out.push_str("  </report>\n</tree>\n");
+    out
+}
+
+fn write_xml_node(node: &TreeNode, columns: &Columns, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent);
+    let tag = match node.kind {
+        EntryKind::Directory => "directory",
+        EntryKind::File => "file",
+        EntryKind::Symlink => "link",
+        EntryKind::CharDevice => "char",
+        EntryKind::BlockDevice => "block",
+        EntryKind::Socket => "socket",
+        EntryKind::Fifo => "fifo",
+    };
+    out.push_str(&format!(
+        "{}<{} name=\"{}\"",
+        pad,
+        tag,
+        xml_escape(&node.name)
+    ));
+    if let Some(target) = &node.link_target {
+        out.push_str(&format!(
+            " target=\"{}\"",
+            xml_escape(&target.to_string_lossy())
+        ));
+    }
+    if node.elided > 0 {
+        out.push_str(&format!(" elided=\"{}\"", node.elided));
+    }
+    for (key, value) in columns.fields(node) {
+        out.push_str(&format!(" {}=\"{}\"", key, xml_escape(&value)));
+    }
+    out.push('>');
+    if node.is_dir {
+        out.push('\n');
+        if node.read_error {
+            out.push_str(&format!("{}  <error>{}</error>\n", pad, READ_ERROR_LABEL));
+        }
+        for child in &node.children {
+            write_xml_node(child, columns, indent + 1, out);
+        }
+        out.push_str(&pad);
+    }
+    out.push_str(&format!("</{}>\n", tag));
+}
+
+/// Stylesheet embedded in HTML output, mirroring the terminal colors
+const HTML_STYLE: &str = "body { font-family: monospace; }
+ul.tree, ul.tree ul { list-style: none; padding-left: 1.5em; }
+summary { cursor: pointer; }
+.dir { color: #2a5db0; font-weight: bold; }
+.sticky { color: #1a7f37; background: #cfe2ff; }
+.symlink { color: #0e8a9e; }
+.setuid { color: #fff; background: #c62828; }
+.setgid { color: #000; background: #f9d648; }
+.device { color: #b08800; font-weight: bold; }
+.special, .world-writable { color: #b08800; }
+.exec { color: #1a7f37; }
+.archive { color: #c62828; }
+.image { color: #a626a4; }
+.media { color: #d63bd6; }
+.elided { color: #777; font-style: italic; }
+";
+
+/// Renders a tree as a self-contained HTML page.
+///
+/// Directories become `<details>`/`<summary>` elements so they can be
+/// collapsed in the browser, and every entry carries the CSS class of its
+/// [`FileClass`].
+fn render_html(node: &TreeNode) -> String {
+    let title = xml_escape(&node.name);
+    let mut out = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n");
+    out.push_str("<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", title));
+    out.push_str(&format!("<style>\n{}</style>\n", HTML_STYLE));
+    out.push_str("</head>\n<body>\n<ul class=\"tree\">\n");
+    write_html_node(node, &mut out);
+    out.push_str(&format!(
+        "</ul>\n<p>{}</p>\n</body>\n</html>\n",
+        xml_escape(&TreeStats::of(node).to_string())
+    ));
+    out
+}
+
+fn write_html_node(node: &TreeNode, out: &mut String) {
+    let class = node.file_class().css_class();
+    let label = xml_escape(&node.display_name());
+    if node.is_dir {
+        let open = if node.expanded { " open" } else { "" };
+        out.push_str(&format!(
+            "<li><details{}><summary class=\"{}\">{}</summary>\n<ul>\n",
+            open, class, label
+        ));
+        for child in &node.children {
+            write_html_node(child, out);
+        }
+        if node.elided > 0 {
+            out.push_str(&format!(
+                "<li class=\"elided\">{}</li>\n",
+                elided_label(node.elided)
+            ));
+        }
+        out.push_str("</ul>\n</details></li>\n");
+    } else {
+        out.push_str(&format!("<li class=\"{}\">{}</li>\n", class, label));
+    }
+}
+
+/// Renders a tree as plain text using the same layout as [`display_tree`],
+/// without colors.
+fn render_text(node: &TreeNode, charset: &Charset) -> String {
+    let mut out = format!("{}\n", node.name);
+    write_text_children(node, charset, &Columns::default(), "", false, &mut out);
+    out
+}
+
+/// Writes the branches below `node`, colorizing names from their file type
+/// and permissions when `colorize` is set
+fn write_text_children(
+    node: &TreeNode,
+    charset: &Charset,
+    columns: &Columns,
+    prefix: &str,
+    colorize: bool,
+    out: &mut String,
+) {
+    let total = node.children.len() + usize::from(node.elided > 0);
+    for (index, child) in node.children.iter().enumerate() {
+        let (connector, new_prefix) = if index == total - 1 {
+            (&charset.last, format!("{}{}", prefix, charset.space))
+        } else {
+            (&charset.branch, format!("{}{}", prefix, charset.vertical))
+        };
+        let name = if colorize {
+            colorize_filename(&child.display_name(), &child.path).to_string()
+        } else {
+            child.display_name()
+        };
+        out.push_str(&format!(
+            "{}{}{}{}\n",
+            prefix,
+            connector,
+            columns.prefix(child),
+            name
+        ));
+        write_text_children(child, charset, columns, &new_prefix, colorize, out);
+    }
+    if node.elided > 0 {
+        out.push_str(&format!(
+            "{}{}{}\n",
+            prefix,
+            charset.last,
+            elided_label(node.elided)
+        ));
+    }
+}
+
+/// Placeholder shown instead of the entries left out by `--filelimit`
+fn elided_label(count: usize) -> String {
+    if count == 1 {
+        "… 1 more entry".to_string()
+    } else {
+        format!("… {} more entries", count)
+    }
+}
+
+/// Renders a tree as a Markdown fenced code block containing the text tree
+fn render_markdown_fenced(node: &TreeNode, charset: &Charset) -> String {
+    format!("```text\n{}```\n", render_text(node, charset))
+}
+
+/// Renders a tree as a Markdown nested bullet list.
+///
+/// Every entry below the root links to its path relative to the root, so the
+/// list can be pasted into a README at the top of the displayed directory.
+fn render_markdown_list(node: &TreeNode) -> String {
+    let mut out = format!("- **{}/**\n", markdown_escape(&node.name));
+    write_markdown_children(node, "", 1, &mut out);
+    out
+}
+
+fn write_markdown_children(node: &TreeNode, rel_path: &str, level: usize, out: &mut String) {
+    let indent = "  ".repeat(level);
+    for child in &node.children {
+        let child_path = if rel_path.is_empty() {
+            child.name.clone()
+        } else {
+            format!("{}/{}", rel_path, child.name)
+        };
+        let mut target = markdown_link_target(&child_path);
+        if child.is_dir {
+            target.push('/');
+        }
+        out.push_str(&format!(
+            "{}- [{}]({})\n",
+            indent,
+            markdown_escape(&child.display_name()),
+            target
+        ));
+        write_markdown_children(child, &child_path, level + 1, out);
+    }
+    if node.elided > 0 {
+        out.push_str(&format!("{}- {}\n", indent, elided_label(node.elided)));
+    }
+}
+
+/// Escapes characters that Markdown would interpret inside link text
+fn markdown_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Percent-encodes the characters of a relative path that break Markdown links
+fn markdown_link_target(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' => out.push_str("%20"),
+            '(' => out.push_str("%28"),
+            ')' => out.push_str("%29"),
+            '<' => out.push_str("%3C"),
+            '>' => out.push_str("%3E"),
+            '#' => out.push_str("%23"),
+            '?' => out.push_str("%3F"),
+            '%' => out.push_str("%25"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Renders a tree as a Graphviz `digraph`.
+///
+/// Every entry is a node and every parent/child relation an edge. Symlinks
+/// get an extra dashed edge to their target: the matching node when the
+/// target lies inside the tree, otherwise a separate plain-text node.
+fn render_dot(node: &TreeNode) -> String {
+    let mut ids = HashMap::new();
+    index_dot_targets(node, &mut 0, &mut ids);
+    let mut out =
+        String::from("digraph tree {\n    rankdir=LR;\n    node [fontname=\"monospace\"];\n");
+    let mut edges = String::new();
+    let mut external = HashMap::new();
+    write_dot_node(node, &ids, &mut 0, &mut external, &mut out, &mut edges);
+    out.push_str(&edges);
+    out.push_str("}\n");
+    out
+}
+
+/// Maps the normalized path of each node to its id, numbered in traversal
+/// order like [`write_dot_node`] does, to resolve symlink targets. Nodes
+/// sharing a path resolve to the first one.
+fn index_dot_targets(node: &TreeNode, next_id: &mut usize, ids: &mut HashMap<PathBuf, usize>) {
+    ids.entry(normalize_path(&node.path)).or_insert(*next_id);
+    *next_id += 1;
+    for child in &node.children {
+        index_dot_targets(child, next_id, ids);
+    }
+}
+
+fn write_dot_node(
+    node: &TreeNode,
+    ids: &HashMap<PathBuf, usize>,
+    next_id: &mut usize,
+    external: &mut HashMap<String, usize>,
+    out: &mut String,
+    edges: &mut String,
+) {
+    let id = *next_id;
+    *next_id += 1;
+    let shape = if node.is_dir { "folder" } else { "box" };
+    let label = if node.is_dir {
+        format!("{}/", node.name)
+    } else {
+        node.name.clone()
+    };
+    out.push_str(&format!(
+        "    n{} [label={}, shape={}];\n",
+        id,
+        dot_string(&label),
+        shape
+    ));
+    if let Some(target) = &node.link_target {
+        let parent = node.path.parent().unwrap_or(Path::new(""));
+        let resolved = normalize_path(&parent.join(target));
+        let target_id = match ids.get(&resolved) {
+            Some(target_id) => format!("n{}", target_id),
+            None => {
+                let target_str = target.to_string_lossy().into_owned();
+                let next = external.len();
+                let index = *external.entry(target_str.clone()).or_insert_with(|| {
+                    out.push_str(&format!(
+                        "    x{} [label={}, shape=plaintext];\n",
+                        next,
+                        dot_string(&target_str)
+                    ));
+                    next
+                });
+                format!("x{}", index)
+            }
+        };
+        edges.push_str(&format!("    n{} -> {} [style=dashed];\n", id, target_id));
+    }
+    for child in &node.children {
+        edges.push_str(&format!("    n{} -> n{};\n", id, next_id));
+        write_dot_node(child, ids, next_id, external, out, edges);
+    }
+    if node.elided > 0 {
+        out.push_str(&format!(
+            "    e{} [label={}, shape=plaintext];\n",
+            id,
+            dot_string(&elided_label(node.elided))
+        ));
+        edges.push_str(&format!("    n{} -> e{};\n", id, id));
+    }
+}
+
+/// Renders a tree as a Mermaid `graph TD` flowchart with one node per entry
+fn render_mermaid_graph(node: &TreeNode) -> String {
+    let mut out = String::from("graph TD\n");
+    out.push_str(&format!(
+        "    n0[{}]\n",
+        mermaid_string(&node.display_name())
+    ));
+    let mut next_id = 1;
+    write_mermaid_edges(node, 0, &mut next_id, &mut out);
+    out
+}
+
+fn write_mermaid_edges(node: &TreeNode, id: usize, next_id: &mut usize, out: &mut String) {
+    for child in &node.children {
+        let child_id = *next_id;
+        *next_id += 1;
+        out.push_str(&format!(
+            "    n{} --> n{}[{}]\n",
+            id,
+            child_id,
+            mermaid_string(&child.display_name())
+        ));
+        write_mermaid_edges(child, child_id, next_id, out);
+    }
+    if node.elided > 0 {
+        out.push_str(&format!(
+            "    n{} --> n{}[{}]\n",
+            id,
+            next_id,
+            mermaid_string(&elided_label(node.elided))
+        ));
+        *next_id += 1;
+    }
+}
+
+/// Renders a tree as a Mermaid `mindmap` with the root as the central node
+fn render_mermaid_mindmap(node: &TreeNode) -> String {
+    let mut out = String::from("mindmap\n");
+    out.push_str(&format!(
+        "  n0(({}))\n",
+        mermaid_string(&node.display_name())
+    ));
+    let mut next_id = 1;
+    write_mermaid_mindmap_children(node, 2, &mut next_id, &mut out);
+    out
+}
+
+fn write_mermaid_mindmap_children(
+    node: &TreeNode,
+    level: usize,
+    next_id: &mut usize,
+    out: &mut String,
+) {
+    for child in &node.children {
+        out.push_str(&format!(
+            "{}n{}[{}]\n",
+            "  ".repeat(level),
+            next_id,
+            mermaid_string(&child.display_name())
+        ));
+        *next_id += 1;
+        write_mermaid_mindmap_children(child, level + 1, next_id, out);
+    }
+    if node.elided > 0 {
+        out.push_str(&format!(
+            "{}n{}[{}]\n",
+            "  ".repeat(level),
+            next_id,
+            mermaid_string(&elided_label(node.elided))
+        ));
+        *next_id += 1;
+    }
+}
+
+/// Quotes a Mermaid node label, replacing double quotes with their entity code
+fn mermaid_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "#quot;"))
+}
+
+/// Renders a tree as a PlantUML work breakdown structure (`@startwbs`)
+fn render_plantuml(node: &TreeNode) -> String {
+    let mut out = String::from("@startwbs\n");
+    write_plantuml_node(node, 1, &mut out);
+    out.push_str("@endwbs\n");
+    out
+}
+
+fn write_plantuml_node(node: &TreeNode, level: usize, out: &mut String) {
+    out.push_str(&format!(
+        "{} {}\n",
+        "*".repeat(level),
+        node.display_name().replace('\n', " ")
+    ));
+    for child in &node.children {
+        write_plantuml_node(child, level + 1, out);
+    }
+    if node.elided > 0 {
+        out.push_str(&format!(
+            "{} {}\n",
+            "*".repeat(level + 1),
+            elided_label(node.elided)
+        ));
+    }
+}
+
+/// Quotes and escapes a string as a Graphviz DOT string
+fn dot_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Lexically normalizes a path by removing `.` components and resolving `..`
+/// against preceding components, without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) {
+                    out.pop();
+                } else {
+                    out.push("..");
+                }
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+/// Renders a tree as a flat table with one row per entry.
+///
+/// Columns are `depth`, `path` (relative to the root), `type`, `size`,
+/// `mode` (octal, Unix only), `mtime` (ISO 8601, UTC) and `elided` (entries
+/// left out by `--filelimit`). A `,` separator
+/// produces RFC 4180 CSV and a tab separator produces TSV.
+fn render_table(node: &TreeNode, separator: char) -> String {
+    let mut out =
+        ["depth", "path", "type", "size", "mode", "mtime", "elided"].join(&separator.to_string());
+    out.push('\n');
+    write_table_rows(node, ".", separator, &mut out);
+    out
+}
+
+fn write_table_rows(node: &TreeNode, rel_path: &str, separator: char, out: &mut String) {
+    let size = node
+        .metadata
+        .as_ref()
+        .map(|m| m.len().to_string())
+        .unwrap_or_default();
+    #[cfg(unix)]
+    let mode = node
+        .metadata
+        .as_ref()
+        .map(|m| format!("{:04o}", m.permissions().mode() & 0o7777))
+        .unwrap_or_default();
+    #[cfg(not(unix))]
+    let mode = String::new();
+    let mtime = node
+        .metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .map(format_timestamp)
+        .unwrap_or_default();
+    let fields = [
+        node.depth.to_string(),
+        rel_path.to_string(),
+        node.kind.as_str().to_string(),
+        size,
+        mode,
+        mtime,
+        if node.elided > 0 {
+            node.elided.to_string()
+        } else {
+            String::new()
+        },
+    ];
+    let row: Vec<String> = fields
+        .iter()
+        .map(|field| table_field(field, separator))
+        .collect();
+    out.push_str(&row.join(&separator.to_string()));
+    out.push('\n');
+    for child in &node.children {
+        let child_path = if node.depth == 0 {
+            child.name.clone()
+        } else {
+            format!("{}/{}", rel_path, child.name)
+        };
+        write_table_rows(child, &child_path, separator, out);
+    }
+}
+
+/// Quotes a CSV field when needed, or escapes control characters in a TSV field
+fn table_field(field: &str, separator: char) -> String {
+    if separator == ',' {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    } else {
+        field
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    }
+}
+
+/// Formats a timestamp as an ISO 8601 date and time in UTC
+fn format_timestamp(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let rem = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Converts a number of days since 1970-01-01 to a (year, month, day) date
+/// in the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097); // day of era [0, 146096]
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365; // year of era [0, 399]
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // day of year [0, 365]
+    let mp = (5 * doy + 2) / 153; // month starting from March [0, 11]
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Converts a (year, month, day) date in the proleptic Gregorian calendar to
+/// a number of days since 1970-01-01; the inverse of [`civil_from_days`].
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400); // year of era [0, 399]
+    let mp = i64::from((month + 9) % 12); // month starting from March [0, 11]
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1; // day of year [0, 365]
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy; // day of era [0, 146096]
+    era * 146_097 + doe - 719_468
+}
+
+/// Escapes the XML special characters of a text or attribute value
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Colorize a file name based on its metadata (permissions and file type).
+///
+/// Colors are applied in order of precedence (first match wins):
+/// 1. Symlinks: Cyan
+/// 2. Setuid files: White on red (highest priority - security sensitive)
+/// 3. Setgid files: Black on yellow (high priority - security sensitive)  
+/// 4. Sticky bit directories: Green on blue (e.g., /tmp)
+/// 5. Regular directories: Blue and bold
+/// 6. Special files (devices, sockets, pipes): Yellow bold
+/// 7. World-writable files: Yellow (warning)
+/// 8. Executable files: Green
+/// 9. Archive files: Red (.zip, .tar, .gz, etc.)
+/// 10. Image files: Magenta (.png, .jpg, etc.)
+/// 11. Audio/video files: Bright magenta (.mp3, .mp4, etc.)
+/// 12. Default: No color
+///
+/// # Arguments
+///
+/// * `name` - The file name to colorize
+/// * `path` - The path to the file (to check metadata)
+///
+/// # Returns
+///
+/// A colored string based on file type and permissions.
+///
+/// # Note
+///
+/// If metadata cannot be read (e.g., permission denied), the name is returned without coloring.
+fn colorize_filename(name: &str, path: &Path) -> ColoredString {
+    match classify_file(path) {
+        FileClass::Symlink => <&str as ColoredCompat>::colored_cyan(name),
+        FileClass::StickyDirectory => <&str as ColoredCompat>::colored_green(name).on_blue(), // Sticky bit directory (e.g., /tmp)
+        FileClass::Directory => <&str as ColoredCompat>::colored_blue(name).bold(),
+        FileClass::Device => <&str as ColoredCompat>::colored_yellow(name).bold(),
+        FileClass::SocketOrFifo => <&str as ColoredCompat>::colored_yellow(name),
+        FileClass::Setuid => <&str as ColoredCompat>::colored_white(name).on_red(), // White text on red background
+        FileClass::Setgid => <&str as ColoredCompat>::colored_black(name).on_yellow(), // Black text on yellow background
+        FileClass::WorldWritable => <&str as ColoredCompat>::colored_yellow(name),
+        FileClass::Executable => <&str as ColoredCompat>::colored_green(name),
+        FileClass::Archive => <&str as ColoredCompat>::colored_red(name),
+        FileClass::Image => <&str as ColoredCompat>::colored_magenta(name),
+        FileClass::Media => <&str as ColoredCompat>::colored_bright_magenta(name),
+        FileClass::Plain => name.normal(),
+    }
+}
+
+/// Classification of an entry by type, permissions and extension.
+///
+/// Shared by terminal coloring and the CSS classes of the HTML export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileClass {
+    Symlink,
+    StickyDirectory,
+    Directory,
+    Device,
+    SocketOrFifo,
+    Setuid,
+    Setgid,
+    WorldWritable,
+    Executable,
+    Archive,
+    Image,
+    Media,
+    Plain,
+}
+
+impl FileClass {
+    /// CSS class name used for this classification in HTML output
+    fn css_class(self) -> &'static str {
+        match self {
+            FileClass::Symlink => "symlink",
+            FileClass::StickyDirectory => "sticky",
+            FileClass::Directory => "dir",
+            FileClass::Device => "device",
+            FileClass::SocketOrFifo => "special",
+            FileClass::Setuid => "setuid",
+            FileClass::Setgid => "setgid",
+            FileClass::WorldWritable => "world-writable",
+            FileClass::Executable => "exec",
+            FileClass::Archive => "archive",
+            FileClass::Image => "image",
+            FileClass::Media => "media",
+            FileClass::Plain => "file",
+        }
+    }
+}
+
+/// Classify a path for coloring, in the precedence order documented on
+/// [`colorize_filename`].
+///
+/// If metadata cannot be read (e.g., permission denied), the path is `Plain`.
+fn classify_file(path: &Path) -> FileClass {
+    // Check if it's a symlink first (using symlink_metadata to avoid following the link)
+    if path
+        .symlink_metadata()
+        .map(|m| m.is_symlink())
+        .unwrap_or(false)
+    {
+        return FileClass::Symlink;
+    }
+
+    // Try to get metadata for the path (follows symlinks if present)
+    let metadata = match path.metadata() {
+        Ok(m) => m,
+        Err(_) => return FileClass::Plain, // If we can't read metadata, return uncolored
+    };
+
+    // Check if it's a directory
+    if metadata.is_dir() {
+        #[cfg(unix)]
+        {
+            let mode = metadata.permissions().mode();
+            // Check for sticky bit on directories
+            if mode & MODE_STICKY_BIT != 0 {
+                return FileClass::StickyDirectory;
+            }
+        }
+        return FileClass::Directory;
+    }
+
+    #[cfg(unix)]
+    {
+        let mode = metadata.permissions().mode();
+        // Check for setuid bit
+        let is_setuid = mode & MODE_SETUID != 0;
+        // Check for setgid bit
+        let is_setgid = mode & MODE_SETGID != 0;
+        // Check if file is executable
+        let is_executable = mode & MODE_EXECUTABLE != 0;
+        // Check if file is writable by others
+        let is_world_writable = mode & MODE_WORLD_WRITABLE != 0;
+        // Check for special file types using file_type()
+        match EntryKind::from_file_type(metadata.file_type()) {
+            // Character or block devices
+            EntryKind::CharDevice | EntryKind::BlockDevice => return FileClass::Device,
+            // Socket or FIFO (named pipe)
+            EntryKind::Socket | EntryKind::Fifo => return FileClass::SocketOrFifo,
+            _ => {}
+        }
+        // Setuid files (highest priority - security sensitive)
+        if is_setuid {
+            return FileClass::Setuid;
+        }
+        // Setgid files (high priority - security sensitive)
+        if is_setgid {
+            return FileClass::Setgid;
+        }
+        // World-writable files (warning)
+        if is_world_writable {
+            return FileClass::WorldWritable;
+        }
+        // Executable files
+        if is_executable {
+            return FileClass::Executable;
+        }
+    }
+
+    // Check file extension for type-based coloring
+    extension_class(path).unwrap_or(FileClass::Plain)
+}
+
+/// Classify a path by its extension as an archive, image or audio/video file
+fn extension_class(path: &Path) -> Option<FileClass> {
+    let ext_lower = path.extension()?.to_str()?.to_lowercase();
+    // Archive files
+    if matches!(
+        ext_lower.as_str(),
+        "zip" | "tar" | "gz" | "bz2" | "xz" | "7z" | "rar" | "tgz" | "tbz2" | "txz"
+    ) {
+        return Some(FileClass::Archive);
+    }
+    // Image files
+    if matches!(
+        ext_lower.as_str(),
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "ico" | "webp" | "tiff" | "tif"
+    ) {
+        return Some(FileClass::Image);
+    }
+    // Audio/video files
+    if matches!(
+        ext_lower.as_str(),
+        "mp3" | "mp4" | "avi" | "mkv" | "flac" | "wav" | "ogg" | "mov" | "wmv" | "webm" | "m4a"
+    ) {
+        return Some(FileClass::Media);
+    }
+    None
+}
+
+// Disambiguate colored methods
+#[allow(dead_code)]
+trait ColoredCompat {
+    fn colored_cyan(s: &str) -> ColoredString;
+    fn colored_green(s: &str) -> ColoredString;
+    fn colored_blue(s: &str) -> ColoredString;
+    fn colored_yellow(s: &str) -> ColoredString;
+    fn colored_white(s: &str) -> ColoredString;
+    fn colored_black(s: &str) -> ColoredString;
+    fn colored_red(s: &str) -> ColoredString;
+    fn colored_magenta(s: &str) -> ColoredString;
+    fn colored_bright_magenta(s: &str) -> ColoredString;
+}
+
+impl ColoredCompat for &str {
+    fn colored_cyan(s: &str) -> ColoredString {
+        colored::Colorize::cyan(s)
+    }
+    fn colored_green(s: &str) -> ColoredString {
+        colored::Colorize::green(s)
+    }
+    fn colored_blue(s: &str) -> ColoredString {
+        colored::Colorize::blue(s)
+    }
+    fn colored_yellow(s: &str) -> ColoredString {
+        colored::Colorize::yellow(s)
+    }
+    fn colored_white(s: &str) -> ColoredString {
+        colored::Colorize::white(s)
+    }
+    fn colored_black(s: &str) -> ColoredString {
+        colored::Colorize::black(s)
+    }
+    fn colored_red(s: &str) -> ColoredString {
+        colored::Colorize::red(s)
+    }
+    fn colored_magenta(s: &str) -> ColoredString {
+        colored::Colorize::magenta(s)
+    }
+    fn colored_bright_magenta(s: &str) -> ColoredString {
+        colored::Colorize::bright_magenta(s)
+    }
+}
+
+// Helper to get mutable reference to the selected node in the real tree using path indices
+fn get_mut_node(tree: &mut TreeNode, sel: usize) -> Option<&mut TreeNode> {
+    let path = tree.get_path_to_flat_index(sel)?;
+    let mut node = tree;
+    for idx in path {
+        node = node.children.get_mut(idx)?;
+    }
+    Some(node)
+}
+
+// Get the path of child indices from root to the selected node in the flat list
+impl TreeNode {
+    fn get_path_to_flat_index(&self, sel: usize) -> Option<Vec<usize>> {
+        // Build flat list with path information
+        let mut flat_with_paths: Vec<Vec<usize>> = vec![];
+        self.flatten_with_paths(&mut flat_with_paths, vec![]);
+        flat_with_paths.get(sel).cloned()
+    }
+
+    fn flatten_with_paths(&self, out: &mut Vec<Vec<usize>>, current_path: Vec<usize>) {
+        out.push(current_path.clone());
+        if self.is_dir && self.expanded {
+            for (i, child) in self.children.iter().enumerate() {
+                let mut child_path = current_path.clone();
+                child_path.push(i);
+                child.flatten_with_paths(out, child_path);
+            }
+            // The `--filelimit` placeholder has no node, so selecting it toggles nothing
+            if self.elided > 0 {
+                let mut placeholder_path = current_path.clone();
+                placeholder_path.push(self.children.len());
+                out.push(placeholder_path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_cli_interactive_flag() {
+        use clap::Parser;
+        let cli = Cli::parse_from(["rutree2", "-i"]);
+        assert!(cli.interactive);
+    }
+
+    use super::*;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+
+    /// Helper function to create a temporary test directory
+    fn create_test_dir() -> (PathBuf, tempfile::TempDir) {
+        let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+        let path = temp_dir.path().to_path_buf();
+        (path, temp_dir)
+    }
+
+    /// The directory and file totals of the summary footer
+    fn entry_counts(tree: &TreeNode) -> (usize, usize) {
+        let stats = TreeStats::of(tree);
+        (stats.directories, stats.files)
+    }
+
+    #[test]
+    fn test_validate_color_valid_values() {
+        assert!(validate_color("auto").is_ok());
+        assert!(validate_color("always").is_ok());
+        assert!(validate_color("never").is_ok());
+    }
+
+    #[test]
+    fn test_validate_color_invalid_value() {
+        let result = validate_color("invalid");
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .contains("must be one of: auto, always, never")
+        );
+    }
+
+    #[test]
+    fn test_validate_output_values() {
+        assert!(validate_output("tree").is_ok());
+        assert!(validate_output("json").is_ok());
+        assert!(validate_output("xml").is_ok());
+        assert!(validate_output("html").is_ok());
+        assert!(validate_output("yaml").is_err());
+    }
+
+    #[test]
+    fn test_json_string_escaping() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(json_string("line\nbreak\u{1}"), "\"line\\nbreak\\u0001\"");
+    }
+
+    #[test]
+    fn test_render_json_nested() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("subdir")).expect("Failed to create directory");
+        File::create(test_dir.join("subdir").join("inner.txt")).expect("Failed to create file");
+        File::create(test_dir.join("top.txt")).expect("Failed to create file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let json = render_json(&tree, &Columns::default());
+        assert!(json.contains("\"name\": \"subdir\""));
+        assert!(json.contains("\"type\": \"directory\""));
+        assert!(json.contains("\"name\": \"inner.txt\""));
+        assert!(json.contains("\"type\": \"file\""));
+        // Files never carry a children array
+        assert_eq!(json.matches("\"children\"").count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_json_symlink_target() {
+        let (test_dir, _temp) = create_test_dir();
+        File::create(test_dir.join("real.txt")).expect("Failed to create file");
+        std::os::unix::fs::symlink("real.txt", test_dir.join("link")).unwrap();
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let json = render_json(&tree, &Columns::default());
+        assert!(json.contains("\"type\": \"symlink\""));
+        assert!(json.contains("\"target\": \"real.txt\""));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("a&b<c>\"d'"), "a&amp;b&lt;c&gt;&quot;d&apos;");
+    }
+
+    #[test]
+    fn test_render_xml_matches_tree_schema() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("empty")).expect("Failed to create directory");
+        File::create(test_dir.join("file.txt")).expect("Failed to create file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let xml = render_xml(&tree, &Columns::default());
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<tree>\n"));
+        assert!(xml.contains("    <directory name=\"empty\">\n    </directory>\n"));
+        assert!(xml.contains("    <file name=\"file.txt\"></file>\n"));
+        assert!(xml.contains("<directories>1</directories>"));
+        assert!(xml.contains("<files>1</files>"));
+        assert!(xml.contains("<symlinks>0</symlinks>"));
+        assert!(xml.ends_with("  </report>\n</tree>\n"));
+    }
+
+    #[test]
+    fn test_classify_file_extensions() {
+        let (test_dir, _temp) = create_test_dir();
+        for (name, class) in [
+            ("a.tar", FileClass::Archive),
+            ("b.PNG", FileClass::Image),
+            ("c.mkv", FileClass::Media),
+            ("d.txt", FileClass::Plain),
+        ] {
+            let file_path = test_dir.join(name);
+            File::create(&file_path).expect("Failed to create file");
+            assert_eq!(classify_file(&file_path), class, "{}", name);
+        }
+        assert_eq!(classify_file(&test_dir), FileClass::Directory);
+    }
+
+    #[test]
+    fn test_render_html_collapsible_directories() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("assets")).expect("Failed to create directory");
+        File::create(test_dir.join("assets").join("logo.png")).expect("Failed to create file");
+        File::create(test_dir.join("bundle.zip")).expect("Failed to create file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let html = render_html(&tree);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        // Only the root directory starts expanded
+        assert_eq!(html.matches("<details open>").count(), 1);
+        assert!(html.contains("<details><summary class=\"dir\">assets/</summary>"));
+        assert!(html.contains("<li class=\"image\">logo.png</li>"));
+        assert!(html.contains("<li class=\"archive\">bundle.zip</li>"));
+        assert!(html.contains("<p>1 directory, 2 files, 0 symlinks, 0 bytes</p>"));
+    }
+
+    #[test]
+    fn test_render_markdown_list_links() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("src")).expect("Failed to create directory");
+        File::create(test_dir.join("src").join("main.rs")).expect("Failed to create file");
+        File::create(test_dir.join("my notes.md")).expect("Failed to create file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let markdown = render_markdown_list(&tree);
+        let lines: Vec<&str> = markdown.lines().collect();
+        assert_eq!(lines[1], "  - [my notes.md](my%20notes.md)");
+        assert_eq!(lines[2], "  - [src/](src/)");
+        assert_eq!(lines[3], "    - [main.rs](src/main.rs)");
+    }
+
+    #[test]
+    fn test_render_markdown_fenced() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("src")).expect("Failed to create directory");
+        File::create(test_dir.join("src").join("main.rs")).expect("Failed to create file");
+        File::create(test_dir.join("Cargo.toml")).expect("Failed to create file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let markdown = render_markdown_fenced(&tree, &Charset::utf8());
+        let expected = format!(
+            "```text\n{}\n├── Cargo.toml\n└── src/\n    └── main.rs\n```\n",
+            tree.name
+        );
+        assert_eq!(markdown, expected);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(Path::new("./a/./b/../c")),
+            PathBuf::from("a/c")
+        );
+        assert_eq!(normalize_path(Path::new("../x")), PathBuf::from("../x"));
+        assert_eq!(
+            normalize_path(Path::new("a/../../y")),
+            PathBuf::from("../y")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_dot_symlink_edges() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("docs")).expect("Failed to create directory");
+        std::os::unix::fs::symlink("docs", test_dir.join("alias")).unwrap();
+        std::os::unix::fs::symlink("/nowhere", test_dir.join("dangling")).unwrap();
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let dot = render_dot(&tree);
+        assert!(dot.starts_with("digraph tree {"));
+        // Preorder ids: root, alias, dangling, docs
+        assert!(dot.contains("n1 [label=\"alias/\", shape=folder];"));
+        assert!(dot.contains("n1 -> n3 [style=dashed];"));
+        assert!(dot.contains("x0 [label=\"/nowhere\", shape=plaintext];"));
+        assert!(dot.contains("n2 -> x0 [style=dashed];"));
+        assert!(dot.contains("n0 -> n3;"));
+    }
+
+    #[test]
+    fn test_render_dot_ids_follow_traversal() {
+        // Nodes whose paths normalize alike still get their own ids
+        let mut root = TreeNode::virtual_node(".", PathBuf::from("."), true, 0);
+        let mut child = TreeNode::virtual_node("a", PathBuf::from("a/.."), true, 1);
+        child.children.push(TreeNode::virtual_node(
+            "b",
+            PathBuf::from("a/../b"),
+            false,
+            2,
+        ));
+        root.children.push(child);
+        root.children
+            .push(TreeNode::virtual_node("b", PathBuf::from("b"), false, 1));
+
+        let dot = render_dot(&root);
+        assert!(dot.contains("n1 [label=\"a/\", shape=folder];"));
+        assert!(dot.contains("n3 [label=\"b\", shape=box];"));
+        assert!(dot.contains("n0 -> n1;\n    n1 -> n2;\n    n0 -> n3;\n"));
+        assert!(!dot.contains("n1 -> n0"));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_709_210_096);
+        assert_eq!(format_timestamp(time), "2024-02-29T12:34:56Z");
+        let time = UNIX_EPOCH - std::time::Duration::from_secs(86_400);
+        assert_eq!(format_timestamp(time), "1969-12-31T00:00:00Z");
+    }
+
+    #[test]
+    fn test_table_field_quoting() {
+        assert_eq!(table_field("plain", ','), "plain");
+        assert_eq!(table_field("a,b", ','), "\"a,b\"");
+        assert_eq!(table_field("say \"hi\"", ','), "\"say \"\"hi\"\"\"");
+        assert_eq!(table_field("a\tb", '\t'), "a\\tb");
+    }
+
+    #[test]
+    fn test_render_table_rows() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("sub")).expect("Failed to create directory");
+        fs::write(test_dir.join("sub").join("data.bin"), b"12345").expect("Failed to write file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let csv = render_table(&tree, ',');
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "depth,path,type,size,mode,mtime,elided");
+        assert!(lines[1].starts_with("0,.,directory,"));
+        assert!(lines[2].starts_with("1,sub,directory,"));
+        assert!(lines[3].starts_with("2,sub/data.bin,file,5,"));
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn test_read_entries_sorted_and_filtered() {
+        let (test_dir, _temp) = create_test_dir();
+        File::create(test_dir.join("b.txt")).expect("Failed to create file");
+        File::create(test_dir.join("a.txt")).expect("Failed to create file");
+        File::create(test_dir.join(".hidden")).expect("Failed to create file");
+
+        let names = |entries: Vec<fs::DirEntry>| -> Vec<String> {
+            entries
+                .iter()
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect()
+        };
+        let mut walk = WalkOptions::new(&test_dir);
+        assert_eq!(
+            names(read_entries(&test_dir, &walk).unwrap()),
+            ["a.txt", "b.txt"]
+        );
+        walk.show_hidden = true;
+        assert_eq!(
+            names(read_entries(&test_dir, &walk).unwrap()),
+            [".hidden", "a.txt", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn test_stream_ndjson_records() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("logs")).expect("Failed to create directory");
+        File::create(test_dir.join("logs").join("app.log")).expect("Failed to create file");
+
+        let mut out = Vec::new();
+        stream_ndjson(
+            &test_dir,
+            &WalkOptions::new(&test_dir),
+            &Columns::default(),
+            &mut out,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"depth\":0,\"path\":\".\","));
+        assert_eq!(
+            lines[1],
+            "{\"depth\":1,\"path\":\"logs\",\"name\":\"logs\",\"type\":\"directory\"}"
+        );
+        assert_eq!(
+            lines[2],
+            "{\"depth\":2,\"path\":\"logs/app.log\",\"name\":\"app.log\",\"type\":\"file\"}"
+        );
+    }
+
+    #[test]
+    fn test_render_mermaid_and_plantuml() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("src")).expect("Failed to create directory");
+        File::create(test_dir.join("src").join("main.rs")).expect("Failed to create file");
+        File::create(test_dir.join("say \"hi\".txt")).expect("Failed to create file");
+
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let graph = render_mermaid_graph(&tree);
+        assert!(graph.starts_with("graph TD\n    n0[\""));
+        assert!(graph.contains("    n0 --> n1[\"say #quot;hi#quot;.txt\"]\n"));
+        assert!(graph.contains("    n0 --> n2[\"src/\"]\n"));
+        assert!(graph.contains("    n2 --> n3[\"main.rs\"]\n"));
+
+        let mindmap = render_mermaid_mindmap(&tree);
+        assert!(mindmap.starts_with("mindmap\n  n0(("));
+        assert!(mindmap.contains("\n    n2[\"src/\"]\n      n3[\"main.rs\"]\n"));
+
+        let wbs = render_plantuml(&tree);
+        assert!(wbs.starts_with("@startwbs\n* "));
+        assert!(wbs.contains("\n** src/\n*** main.rs\n"));
+        assert!(wbs.ends_with("@endwbs\n"));
+    }
+
+    #[test]
+    fn test_parse_charset_presets() {
+        assert_eq!(parse_charset("utf8").unwrap(), Charset::utf8());
+        let ascii = parse_charset("ascii").unwrap();
+        assert_eq!(ascii.branch, "|-- ");
+        assert_eq!(ascii.last, "`-- ");
+        assert_eq!(ascii.vertical, "|   ");
+        assert_eq!(ascii.space, "    ");
+        assert!(parse_charset("heavy").is_ok());
+        assert!(parse_charset("rounded").is_ok());
+        assert!(parse_charset("fancy").is_err());
+    }
+
+    #[test]
+    fn test_parse_charset_custom_pads_glyphs() {
+        let custom = parse_charset("custom:+-,\\-,|").unwrap();
+        assert_eq!(custom.branch, "+-");
+        assert_eq!(custom.last, "\\-");
+        assert_eq!(custom.vertical, "| ");
+        assert_eq!(custom.space, "  ");
+        assert!(parse_charset("custom:a,b").is_err());
+    }
+
+    #[test]
+    fn test_flatten_uses_charset() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir(test_dir.join("dir")).expect("Failed to create directory");
+        File::create(test_dir.join("file")).expect("Failed to create file");
+
+        let mut tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        tree.children[0].expanded = true;
+        let prefixes: Vec<String> = tree
+            .flatten(&Charset::ascii())
+            .into_iter()
+            .map(|(prefix, _)| prefix)
+            .collect();
+        assert_eq!(prefixes, ["", "|-- ", "`-- "]);
+        let text = render_text(&tree, &Charset::ascii());
+        assert!(text.ends_with("|-- dir/\n`-- file\n"));
+    }
+
+    #[test]
+    fn test_cli_fromfile_flags() {
+        let cli = Cli::parse_from(["rutree2", "--fromfile", "-0", "list.txt"]);
+        assert!(cli.fromfile);
+        assert!(cli.null);
+        assert_eq!(cli.path, PathBuf::from("list.txt"));
+        // NUL separation only makes sense for path listings
+        assert!(Cli::try_parse_from(["rutree2", "--null"]).is_err());
+    }
+
+    #[test]
+    fn test_read_listing_separators() {
+        let (test_dir, _temp) = create_test_dir();
+        let lines = test_dir.join("lines.txt");
+        fs::write(&lines, "a/b.txt\r\n\nc/\n").expect("Failed to write file");
+        assert_eq!(read_listing(&lines, false).unwrap(), ["a/b.txt", "c/"]);
+
+        let nul = test_dir.join("nul.txt");
+        fs::write(&nul, "a b\nc\0d\0").expect("Failed to write file");
+        assert_eq!(read_listing(&nul, true).unwrap(), ["a b\nc", "d"]);
+    }
+
+    #[test]
+    fn test_tree_from_listing() {
+        let listing: Vec<String> = [
+            "./src/main.rs",
+            "Cargo.toml",
+            "src/lib/",
+            ".git/HEAD",
+            "docs/a/b/c.md",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let tree = TreeNode::from_listing(".", &listing, &WalkOptions::new(Path::new(".")));
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            ".\n|-- Cargo.toml\n|-- docs/\n|   `-- a/\n|       `-- b/\n|           `-- c.md\n`-- src/\n    |-- lib/\n    `-- main.rs\n"
+        );
+        assert_eq!(entry_counts(&tree), (5, 3));
+
+        let walk = WalkOptions {
+            show_hidden: true,
+            max_depth: Some(1),
+            ..WalkOptions::new(Path::new("."))
+        };
+        let shallow = TreeNode::from_listing(".", &listing, &walk);
+        let names: Vec<&str> = shallow.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, [".git", "Cargo.toml", "docs", "src"]);
+        assert!(shallow.children.iter().all(|c| c.children.is_empty()));
+        assert!(shallow.children[0].is_dir);
+
+        assert_eq!(
+            listing_components("./a/../b/./c"),
+            Some(vec!["b".to_string(), "c".to_string()])
+        );
+        assert_eq!(listing_components("a/../../x"), None);
+        let dotted = ["a/../b".to_string(), "../x".to_string()];
+        let tree = TreeNode::from_listing(".", &dotted, &WalkOptions::new(Path::new(".")));
+        assert_eq!(render_text(&tree, &Charset::ascii()), ".\n`-- b\n");
+
+        let absolute = ["/etc/passwd".to_string(), "/etc/photo.png".to_string()];
+        let tree = TreeNode::from_listing(".", &absolute, &WalkOptions::new(Path::new(".")));
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            ".\n`-- etc/\n    |-- passwd\n    `-- photo.png\n"
+        );
+        let html = render_html(&tree);
+        assert!(html.contains("<summary class=\"dir\">etc/</summary>"));
+        assert!(html.contains("<li class=\"image\">photo.png</li>"));
+    }
+
+    #[test]
+    fn test_glob_match_syntax() {
+        let glob = |p: &str, t: &str| {
+            glob_match(
+                &p.chars().collect::<Vec<_>>(),
+                &t.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(glob("*.rs", "main.rs"));
+        assert!(!glob("*.rs", "src/main.rs"));
+        assert!(glob("src/*.rs", "src/main.rs"));
+        assert!(glob("**/*.rs", "main.rs"));
+        assert!(glob("**/*.rs", "a/b/main.rs"));
+        assert!(glob("a/**", "a/b/c"));
+        assert!(glob("file?.txt", "file1.txt"));
+        assert!(glob("v[0-9].sql", "v7.sql"));
+        assert!(!glob("v[!0-9].sql", "v7.sql"));
+        assert!(glob("[]x]", "]"));
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "a"));
+        assert!(glob("a[b", "a[b"));
+    }
+
+    #[test]
+    fn test_glob_pattern_modes() {
+        assert!(GlobPattern::new("target", false).matches("sub/target", true));
+        assert!(GlobPattern::new("/target", false).matches("target", true));
+        assert!(!GlobPattern::new("/target", false).matches("sub/target", true));
+        assert!(!GlobPattern::new("build/", false).matches("build", false));
+        assert!(GlobPattern::new("build/", false).matches("build", true));
+        assert!(!GlobPattern::new("*.RS", false).matches("main.rs", false));
+        assert!(GlobPattern::new("*.RS", true).matches("main.rs", false));
+    }
+
+    #[test]
+    fn test_walk_options_prune_excluded_directories() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("src")).expect("Failed to create directory");
+        fs::create_dir_all(test_dir.join("target/debug")).expect("Failed to create directory");
+        File::create(test_dir.join("src/main.rs")).expect("Failed to create file");
+        File::create(test_dir.join("src/notes.txt")).expect("Failed to create file");
+        File::create(test_dir.join("target/debug/app.rs")).expect("Failed to create file");
+
+        let cli = Cli::parse_from([
+            "rutree2".as_ref(),
+            "-P".as_ref(),
+            "*.rs".as_ref(),
+            "-I".as_ref(),
+            "target|node_modules".as_ref(),
+            test_dir.as_os_str(),
+        ]);
+        let walk = WalkOptions::from_cli(&cli);
+        assert_eq!(walk.exclude.len(), 2);
+        let tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!("{}\n`-- src/\n    `-- main.rs\n", tree.name)
+        );
+    }
+
+    #[test]
+    fn test_regex_filters_on_name_and_path() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("db/migrations")).expect("Failed to create directory");
+        fs::create_dir_all(test_dir.join("db/seeds")).expect("Failed to create directory");
+        for file in [
+            "db/migrations/V1__init.sql",
+            "db/migrations/V2__users.sql",
+            "db/migrations/notes.sql",
+            "db/seeds/V1__seed.sql",
+        ] {
+            File::create(test_dir.join(file)).expect("Failed to create file");
+        }
+
+        let parse = |args: &[&str]| {
+            let mut argv = vec!["rutree2".to_string()];
+            argv.extend(args.iter().map(|a| a.to_string()));
+            argv.push(test_dir.display().to_string());
+            Cli::try_parse_from(argv)
+        };
+        let cli = parse(&[
+            "--match-regex",
+            r"^V\d+__\w+\.sql$",
+            "--exclude-regex",
+            "^seeds$",
+        ])
+        .unwrap();
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!(
+                "{}\n`-- db/\n    `-- migrations/\n        |-- V1__init.sql\n        `-- V2__users.sql\n",
+                tree.name
+            )
+        );
+
+        let cli = parse(&["--match-regex", "^db/seeds/", "--regex-on", "path"]).unwrap();
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+        let db = &tree.children[0];
+        assert!(db.children[0].children.is_empty());
+        assert_eq!(db.children[1].children[0].name, "V1__seed.sql");
+
+        assert!(parse(&["--match-regex", "("]).is_err());
+        assert!(parse(&["--regex-on", "basename"]).is_err());
+    }
+
+    #[test]
+    fn test_gitignore_rules_and_precedence() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join(".git/info")).expect("Failed to create directory");
+        fs::create_dir_all(test_dir.join("target/debug")).expect("Failed to create directory");
+        fs::create_dir_all(test_dir.join("sub")).expect("Failed to create directory");
+        fs::write(
+            test_dir.join(".gitignore"),
+            "# build\ntarget/\n*.log\n!keep.log\n",
+        )
+        .expect("Failed to write file");
+        fs::write(test_dir.join(".git/info/exclude"), "scratch.txt\n")
+            .expect("Failed to write file");
+        fs::write(test_dir.join("sub/.gitignore"), "/local.txt\n").expect("Failed to write file");
+        fs::write(test_dir.join(".rutreeignore"), "secret.txt\n").expect("Failed to write file");
+        for file in [
+            "a.log",
+            "keep.log",
+            "local.txt",
+            "scratch.txt",
+            "secret.txt",
+            "sub/local.txt",
+            "sub/other.txt",
+            "target/debug/app",
+        ] {
+            File::create(test_dir.join(file)).expect("Failed to create file");
+        }
+
+        let walk = WalkOptions {
+            show_hidden: true,
+            ignore_files: Some(IgnoreFiles::new(&test_dir)),
+            ..WalkOptions::new(&test_dir)
+        };
+        let tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        let names: Vec<_> = tree.children.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                ".gitignore",
+                ".rutreeignore",
+                "keep.log",
+                "local.txt",
+                "sub"
+            ]
+        );
+        let sub: Vec<_> = tree.children[4]
+            .children
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        assert_eq!(sub, [".gitignore", "other.txt"]);
+    }
+
+    #[test]
+    fn test_git_config_excludes_file() {
+        let config = "[user]\n\tname = x\n[core]\n\texcludesFile = \"~/.gitignore_global\"\n";
+        assert_eq!(
+            git_config_excludes_file(config).as_deref(),
+            Some("~/.gitignore_global")
+        );
+        assert_eq!(git_config_excludes_file("[user]\nexcludesfile = x\n"), None);
+    }
+
+    #[test]
+    fn test_type_filter() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("a/b")).expect("Failed to create dir");
+        File::create(test_dir.join("a/file.txt")).expect("Failed to create file");
+
+        let cli = Cli::parse_from([
+            "rutree2".as_ref(),
+            "--type".as_ref(),
+            "d".as_ref(),
+            test_dir.as_os_str(),
+        ]);
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+        assert_eq!(entry_counts(&tree), (2, 0));
+
+        assert_eq!(parse_entry_kind("p"), Ok(EntryKind::Fifo));
+        assert!(Cli::try_parse_from(["rutree2", "--type", "f,x"]).is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("file.txt", test_dir.join("a/link"))
+                .expect("Failed to create symlink");
+            let cli = Cli::parse_from([
+                "rutree2".as_ref(),
+                "--type".as_ref(),
+                "l,s".as_ref(),
+                "--prune".as_ref(),
+                test_dir.as_os_str(),
+            ]);
+            let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+            assert_eq!(
+                render_text(&tree, &Charset::ascii()),
+                format!("{}\n`-- a/\n    `-- link -> file.txt\n", tree.name)
+            );
+
+            std::os::unix::fs::symlink("a", test_dir.join("alias"))
+                .expect("Failed to create symlink");
+            let type_walk = |types| WalkOptions {
+                types,
+                ..WalkOptions::new(&test_dir)
+            };
+            let tree =
+                TreeNode::from_path(&test_dir, &type_walk(vec![EntryKind::File]), 0).unwrap();
+            assert_eq!(entry_counts(&tree), (2, 1));
+            let tree =
+                TreeNode::from_path(&test_dir, &type_walk(vec![EntryKind::Directory]), 0).unwrap();
+            assert_eq!(entry_counts(&tree), (2, 0));
+            let tree =
+                TreeNode::from_path(&test_dir, &type_walk(vec![EntryKind::Symlink]), 0).unwrap();
+            assert_eq!(tree.children[1].name, "alias");
+            assert_eq!(tree.children[1].children[1].name, "link");
+        }
+    }
+
+    #[test]
+    fn test_parse_size_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512B"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("10k"), Ok(10 * 1024));
+        assert_eq!(parse_size("10KB"), Ok(10_000));
+        assert_eq!(parse_size("1.5GiB"), Ok(1024 * 1024 * 1536));
+        assert_eq!(parse_size("2M"), Ok(2 * 1024 * 1024));
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+
+    #[test]
+    fn test_size_filters_keep_directories_leading_to_matches() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("assets/small")).expect("Failed to create dir");
+        fs::write(test_dir.join("assets/big.bin"), vec![0u8; 4096]).expect("Failed to write file");
+        fs::write(test_dir.join("assets/mid.bin"), vec![0u8; 2048]).expect("Failed to write file");
+        fs::write(test_dir.join("assets/small/tiny.txt"), b"x").expect("Failed to write file");
+
+        let walk = WalkOptions {
+            min_size: Some(2048),
+            max_size: Some(3000),
+            prune: true,
+            ..WalkOptions::new(&test_dir)
+        };
+        let tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!("{}\n`-- assets/\n    `-- mid.bin\n", tree.name)
+        );
+    }
+
+    #[test]
+    fn test_parse_time_specs() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2w"), Some(Duration::from_secs(14 * 86_400)));
+        assert_eq!(parse_duration("3"), None);
+        assert_eq!(parse_duration("3x"), None);
+        assert_eq!(parse_duration(""), None);
+
+        let at = |secs: u64| Some(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(parse_datetime("2024-05-01"), at(1_714_521_600));
+        assert_eq!(parse_datetime("2024-05-01T12:00:00Z"), at(1_714_564_800));
+        assert_eq!(parse_datetime("2024-05-01 12:30"), at(1_714_566_600));
+        assert_eq!(parse_datetime("2024-02-30"), None);
+        assert_eq!(parse_datetime("2024-05-01T25:00"), None);
+        assert_eq!(parse_datetime("9999999999999-01-01"), None);
+        assert_eq!(parse_datetime("2024-4294967295-01"), None);
+        assert!(parse_datetime("9999-12-31T23:59:59").is_some());
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(days_from_civil(1900, 3, 1)), (1900, 3, 1));
+
+        assert!(parse_time_spec("Cargo.toml").is_ok());
+        assert!(parse_time_spec("no-such-reference-file").is_err());
+    }
+
+    #[test]
+    fn test_time_filters_on_mtime() {
+        let (test_dir, _temp) = create_test_dir();
+        let old = File::create(test_dir.join("old.log")).expect("Failed to create file");
+        old.set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+            .expect("Failed to set mtime");
+        File::create(test_dir.join("new.log")).expect("Failed to create file");
+
+        let names = |walk: &WalkOptions| {
+            let tree = TreeNode::from_path(&test_dir, walk, 0).unwrap();
+            tree.children
+                .iter()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>()
+        };
+        let newer = WalkOptions {
+            newer_than: parse_time_spec("1d").ok(),
+            ..WalkOptions::new(&test_dir)
+        };
+        assert_eq!(names(&newer), ["new.log"]);
+        let older = WalkOptions {
+            older_than: parse_datetime("2020-01-01"),
+            ..WalkOptions::new(&test_dir)
+        };
+        assert_eq!(names(&older), ["old.log"]);
+    }
+
+    #[test]
+    fn test_parse_perm_and_owner() {
+        assert_eq!(parse_perm("644"), Ok(PermMatch::Exact(0o644)));
+        assert_eq!(parse_perm("-4000"), Ok(PermMatch::All(0o4000)));
+        assert_eq!(parse_perm("/022"), Ok(PermMatch::Any(0o022)));
+        assert_eq!(parse_perm("setgid"), Ok(PermMatch::Any(MODE_SETGID)));
+        assert!(parse_perm("--4000").is_err());
+        assert!(parse_perm("/9").is_err());
+        assert!(parse_perm("17777").is_err());
+
+        assert!(PermMatch::Exact(0o644).matches(0o100644));
+        assert!(!PermMatch::Exact(0o644).matches(0o100664));
+        assert!(PermMatch::All(0o6000).matches(0o6755));
+        assert!(!PermMatch::All(0o6000).matches(0o4755));
+        assert!(PermMatch::Any(0o6000).matches(0o4755));
+
+        assert_eq!(parse_user("1000"), Ok(1000));
+        assert!(parse_group("no-such-group-name").is_err());
+        #[cfg(unix)]
+        assert_eq!(parse_user("root"), Ok(0));
+
+        // Names sharing an id are all found, and the first one is displayed
+        let (test_dir, _temp) = create_test_dir();
+        let passwd = test_dir.join("passwd");
+        fs::write(
+            &passwd,
+            "root:x:0:0::/root:/bin/sh\ntoor:x:0:0::/root:/bin/sh\n# comment\n",
+        )
+        .expect("Failed to write file");
+        let passwd = passwd.to_str().unwrap();
+        assert_eq!(lookup_id(passwd, "toor"), Some(0));
+        assert_eq!(lookup_id(passwd, "comment"), None);
+        assert_eq!(
+            read_id_names(passwd).get(&0).map(String::as_str),
+            Some("root")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_perm_filter_finds_special_bits() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("bin")).expect("Failed to create dir");
+        for (file, mode) in [("bin/su", 0o4755), ("bin/ls", 0o755), ("shared.txt", 0o666)] {
+            let path = test_dir.join(file);
+            File::create(&path).expect("Failed to create file");
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode))
+                .expect("Failed to set permissions");
+        }
+        std::os::unix::fs::symlink("shared.txt", test_dir.join("link"))
+            .expect("Failed to create symlink");
+
+        let cli = Cli::parse_from([
+            "rutree2".as_ref(),
+            "--perm".as_ref(),
+            "setuid,world-writable".as_ref(),
+            test_dir.as_os_str(),
+        ]);
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!("{}\n|-- bin/\n|   `-- su\n`-- shared.txt\n", tree.name)
+        );
+
+        // Directories are tested too, and kept by --prune when they match
+        fs::create_dir(test_dir.join("tmp")).expect("Failed to create dir");
+        fs::set_permissions(test_dir.join("tmp"), fs::Permissions::from_mode(0o1777))
+            .expect("Failed to set permissions");
+        for perm in ["sticky", "world-writable"] {
+            let cli = Cli::parse_from([
+                "rutree2".as_ref(),
+                "--perm".as_ref(),
+                perm.as_ref(),
+                "--prune".as_ref(),
+                test_dir.as_os_str(),
+            ]);
+            let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+            let expected = if perm == "sticky" {
+                format!("{}\n`-- tmp/\n", tree.name)
+            } else {
+                format!("{}\n|-- shared.txt\n`-- tmp/\n", tree.name)
+            };
+            assert_eq!(render_text(&tree, &Charset::ascii()), expected);
         }
     }
 
-    // Get the file name
-    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or(".");
+    #[test]
+    fn test_contains_filter_with_counts() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("src")).expect("Failed to create dir");
+        fs::write(
+            test_dir.join("src/lib.rs"),
+            "fn parse_a() {}\nfn parse_b() {}\n",
+        )
+        .expect("Failed to write file");
+        fs::write(test_dir.join("src/main.rs"), "fn main() { parse_a(); }\n")
+            .expect("Failed to write file");
+        fs::write(test_dir.join("notes.txt"), "nothing here\n").expect("Failed to write file");
+        fs::write(test_dir.join("blob.bin"), b"\0fn parse_c() {}\n").expect("Failed to write file");
 
-    // Print current directory/file
-    if current_depth == 0 {
-        let colored_name = colorize_filename(name, path);
-        println!("{}", colored_name);
-    }
+        let cli = Cli::parse_from([
+            "rutree2".as_ref(),
+            "--contains".as_ref(),
+            r"parse_\w+\(".as_ref(),
+            "--count".as_ref(),
+            "--prune".as_ref(),
+            test_dir.as_os_str(),
+        ]);
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::from_cli(&cli), 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!(
+                "{}\n`-- src/\n    |-- lib.rs (2 matches)\n    `-- main.rs (1 match)\n",
+                tree.name
+            )
+        );
+        assert!(render_json(&tree, &Columns::default()).contains("\"matches\": 2"));
+        assert!(Cli::try_parse_from(["rutree2", "--count"]).is_err());
 
-    // Check if it's a directory
-    if path.is_dir() {
-        // Read directory entries
-        let mut entries: Vec<_> = fs::read_dir(path)?
-            .filter_map(Result::ok)
-            .filter(|entry| {
-                // Filter hidden files if needed
-                #[allow(clippy::collapsible_if)]
-                if !show_hidden {
-                    if let Some(name) = entry.file_name().to_str() {
-                        return !name.starts_with('.');
-                    }
-                }
-                true
-            })
-            .collect();
+        // Lines past the binary check are streamed, and CRLF endings are stripped
+        let long = test_dir.join("long.log");
+        fs::write(&long, format!("{}parse_d\r\nparse_e\n", "-\n".repeat(5000)))
+            .expect("Failed to write file");
+        let regex = Regex::new("parse_.$").unwrap();
+        assert_eq!(count_matching_lines(&long, &regex, usize::MAX), 2);
+        assert_eq!(count_matching_lines(&long, &regex, 1), 1);
 
-        // Sort entries by name
-        entries.sort_by_key(|entry| entry.file_name());
+        // Counts are only kept until their entry becomes a node
+        let walk = WalkOptions {
+            include: vec![GlobPattern::new("*.rs", false)],
+            file_limit: Some(1),
+            prune: false,
+            ..WalkOptions::from_cli(&cli)
+        };
+        let mut tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        tree.compute_du(&walk, DuMode::Apparent);
+        assert_eq!(tree.children[0].children[0].matches, Some(2));
+        assert!(walk.match_counts.borrow().is_empty());
+    }
 
-        let total = entries.len();
-        for (index, entry) in entries.iter().enumerate() {
-            let path = entry.path();
-            let is_last = index == total - 1;
+    #[test]
+    fn test_filelimit_elides_extra_entries() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("cache")).expect("Failed to create dir");
+        for i in 0..5 {
+            File::create(test_dir.join(format!("cache/{}.bin", i))).expect("Failed to create file");
+        }
+        File::create(test_dir.join("readme")).expect("Failed to create file");
 
-            let (connector, new_prefix) = if is_last {
-                ("└── ", format!("{}    ", prefix))
-            } else {
-                ("├── ", format!("{}│   ", prefix))
-            };
+        let walk = WalkOptions {
+            file_limit: Some(2),
+            ..WalkOptions::new(&test_dir)
+        };
+        let mut tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!(
+                "{}\n|-- cache/\n|   |-- 0.bin\n|   |-- 1.bin\n|   `-- … 3 more entries\n`-- readme\n",
+                tree.name
+            )
+        );
+        assert!(render_json(&tree, &Columns::default()).contains("\"elided\": 3"));
+        let xml = render_xml(&tree, &Columns::default());
+        assert!(xml.contains("<directory name=\"cache\" elided=\"3\">"));
+        assert!(xml.contains("<elided>3</elided>"));
+        assert!(render_table(&tree, ',').contains("\n1,cache,directory,"));
+        assert!(
+            render_table(&tree, ',')
+                .lines()
+                .nth(2)
+                .unwrap()
+                .ends_with(",3")
+        );
+        assert!(render_dot(&tree).contains("n1 -> e1;"));
+        assert!(render_mermaid_graph(&tree).contains("n1 --> n4[\"… 3 more entries\"]"));
+        assert!(render_mermaid_mindmap(&tree).contains("      n4[\"… 3 more entries\"]"));
+        assert!(render_plantuml(&tree).contains("\n*** … 3 more entries\n"));
+        tree.children[0].expanded = true;
+        let flat = tree.flatten(&Charset::ascii());
+        assert_eq!(flat.len(), 6);
+        assert_eq!(flat[4].0, "|   `-- ");
+        assert_eq!(tree.get_path_to_flat_index(4), Some(vec![0, 2]));
 
-            let name = entry.file_name();
-            let name_str = name.to_string_lossy();
-
-            // Get metadata once and reuse it
-            let symlink_meta = path.symlink_metadata().ok();
-            let is_symlink = symlink_meta.as_ref().is_some_and(|m| m.is_symlink());
-
-            // Check if it's a symlink
-            let display_name = if is_symlink {
-                // Read the symlink target
-                if let Ok(target) = fs::read_link(&path) {
-                    let target_str = target.display();
-                    // Add directory indicator for symlinks that point to directories
-                    // Use path.is_dir() which follows symlinks to determine if target is a directory
-                    if path.is_dir() {
-                        format!("{}/ -> {}", name_str, target_str)
-                    } else {
-                        format!("{} -> {}", name_str, target_str)
-                    }
-                } else {
-                    // Broken symlink
-                    format!("{} -> [broken link]", name_str)
-                }
-            } else if path.is_dir() {
-                format!("{}/", name_str)
-            } else {
-                name_str.to_string()
-            };
+        let mut out = Vec::new();
+        stream_ndjson(&test_dir, &walk, &Columns::default(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\"name\":\"cache\",\"type\":\"directory\",\"elided\":3}"));
 
-            // Colorize the filename based on permissions and type
-            let colored_name = colorize_filename(&display_name, &path);
+        // The limit applies to the entries left by --prune
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("e1")).expect("Failed to create dir");
+        fs::create_dir_all(test_dir.join("e2")).expect("Failed to create dir");
+        File::create(test_dir.join("z.txt")).expect("Failed to create file");
+        let walk = WalkOptions {
+            include: vec![GlobPattern::new("*.txt", false)],
+            prune: true,
+            file_limit: Some(1),
+            ..WalkOptions::new(&test_dir)
+        };
+        let tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!("{}\n`-- z.txt\n", tree.name)
+        );
+        let listing = ["e1/".to_string(), "e2/".to_string(), "z.txt".to_string()];
+        let tree = TreeNode::from_listing(".", &listing, &walk);
+        assert_eq!(render_text(&tree, &Charset::ascii()), ".\n`-- z.txt\n");
+    }
 
-            println!("{}{}{}", prefix, connector, colored_name);
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_directory_is_marked() {
+        use std::os::unix::fs::PermissionsExt;
 
-            // Recursively display subdirectories
-            if path.is_dir() {
-                display_tree(
-                    &path,
-                    show_hidden,
-                    max_depth,
-                    &new_prefix,
-                    current_depth + 1,
-                )?;
-            }
+        let (test_dir, _temp) = create_test_dir();
+        let locked = test_dir.join("locked");
+        fs::create_dir(&locked).expect("Failed to create dir");
+        File::create(locked.join("secret")).expect("Failed to create file");
+        File::create(test_dir.join("readme")).expect("Failed to create file");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let readable = fs::read_dir(&locked).is_ok();
+        let walk = WalkOptions::new(&test_dir);
+        let tree = TreeNode::from_path(&test_dir, &walk, 0);
+        let mut out = Vec::new();
+        let streamed = stream_ndjson(&test_dir, &walk, &Columns::default(), &mut out);
+        let root_failed = TreeNode::from_path(&locked, &walk, 0).is_err();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        // Permission bits do not apply to privileged users
+        if readable {
+            return;
         }
+
+        let tree = tree.unwrap();
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            format!(
+                "{}\n|-- locked/ [error opening dir]\n`-- readme\n",
+                tree.name
+            )
+        );
+        assert!(
+            render_json(&tree, &Columns::default()).contains("\"error\": \"error opening dir\"")
+        );
+        streamed.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\"name\":\"locked\",\"type\":\"directory\",\"error\":"));
+        assert!(root_failed);
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_prune_removes_directories_emptied_by_filters() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("src/main/java/com/acme")).expect("Failed to create dir");
+        fs::create_dir_all(test_dir.join("src/test/java/com/acme")).expect("Failed to create dir");
+        File::create(test_dir.join("src/main/java/com/acme/App.java"))
+            .expect("Failed to create file");
+        File::create(test_dir.join("src/test/java/com/acme/notes.txt"))
+            .expect("Failed to create file");
 
-/// Colorize a file name based on its metadata (permissions and file type).
-///
-/// Colors are applied in order of precedence (first match wins):
-/// 1. Symlinks: Cyan
-/// 2. Setuid files: White on red (highest priority - security sensitive)
-/// 3. Setgid files: Black on yellow (high priority - security sensitive)  
-/// 4. Sticky bit directories: Green on blue (e.g., /tmp)
-/// 5. Regular directories: Blue and bold
-/// 6. Special files (devices, sockets, pipes): Yellow bold
-/// 7. World-writable files: Yellow (warning)
-/// 8. Executable files: Green
-/// 9. Archive files: Red (.zip, .tar, .gz, etc.)
-/// 10. Image files: Magenta (.png, .jpg, etc.)
-/// 11. Audio/video files: Bright magenta (.mp3, .mp4, etc.)
-/// 12. Default: No color
-///
-/// # Arguments
-///
-/// * `name` - The file name to colorize
-/// * `path` - The path to the file (to check metadata)
-///
-/// # Returns
-///
-/// A colored string based on file type and permissions.
-///
-/// # Note
-///
-/// If metadata cannot be read (e.g., permission denied), the name is returned without coloring.
-fn colorize_filename(name: &str, path: &Path) -> ColoredString {
-    // Check if it's a symlink first (using symlink_metadata to avoid following the link)
-    if path
-        .symlink_metadata()
-        .map(|m| m.is_symlink())
-        .unwrap_or(false)
-    {
-        return <&str as ColoredCompat>::colored_cyan(name);
+        let mut walk = WalkOptions {
+            include: vec![GlobPattern::new("*.java", false)],
+            prune: true,
+            ..WalkOptions::new(&test_dir)
+        };
+        let tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        assert_eq!(entry_counts(&tree), (5, 1));
+        assert_eq!(tree.children[0].children[0].name, "main");
+
+        // Directories at the depth limit were never read and are kept
+        walk.max_depth = Some(2);
+        let tree = TreeNode::from_path(&test_dir, &walk, 0).unwrap();
+        assert_eq!(entry_counts(&tree), (3, 0));
+
+        let listing = ["a/b/".to_string(), "c/d.java".to_string()];
+        walk.max_depth = None;
+        let tree = TreeNode::from_listing(".", &listing, &walk);
+        assert_eq!(
+            render_text(&tree, &Charset::ascii()),
+            ".\n`-- c/\n    `-- d.java\n"
+        );
     }
 
-    // Try to get metadata for the path (follows symlinks if present)
-    let metadata = match path.metadata() {
-        Ok(m) => m,
-        Err(_) => return name.normal(), // If we can't read metadata, return uncolored
-    };
+    #[test]
+    fn test_size_columns() {
+        assert_eq!(human_size(0, 1024), "0");
+        assert_eq!(human_size(1023, 1024), "1023");
+        assert_eq!(human_size(4096, 1024), "4.0K");
+        assert_eq!(human_size(12 * 1024 * 1024, 1024), "12M");
+        assert_eq!(human_size(1023 * 1024 + 1000, 1024), "1.0M");
+        assert_eq!(human_size(1500, 1000), "1.5K");
+        assert_eq!(SizeFormat::Bytes.format(42), "         42");
+        assert_eq!(SizeFormat::Binary.format(42), "  42");
 
-    // Check if it's a directory
-    if metadata.is_dir() {
+        let cli = Cli::parse_from(["rutree2", "-h", "--si"]);
+        assert_eq!(Columns::from_cli(&cli).size, Some(SizeFormat::Decimal));
+        assert!(Cli::try_parse_from(["rutree2", "--help"]).is_err());
+
+        let (test_dir, _temp) = create_test_dir();
+        fs::write(test_dir.join("data.bin"), vec![0u8; 2048]).expect("Failed to write file");
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let columns = Columns {
+            size: Some(SizeFormat::Binary),
+            ..Columns::default()
+        };
+        let mut out = String::new();
+        write_text_children(&tree, &Charset::ascii(), &columns, "", false, &mut out);
+        assert_eq!(out, "`-- [2.0K]  data.bin\n");
+
+        // Structured outputs carry the same columns, unformatted
+        let columns = Columns {
+            long: vec![LongColumn::Mode],
+            ..columns
+        };
+        let json = render_json(&tree, &columns);
+        assert!(json.contains("\"name\": \"data.bin\",\n      \"type\": \"file\",\n"));
+        assert!(json.contains("\"size\": 2048"));
         #[cfg(unix)]
         {
-            let mode = metadata.permissions().mode();
-            // Check for sticky bit on directories
-            if mode & MODE_STICKY_BIT != 0 {
-                return <&str as ColoredCompat>::colored_green(name).on_blue(); // Sticky bit directory (e.g., /tmp)
-            }
+            let xml = render_xml(&tree, &columns);
+            assert!(xml.contains("name=\"data.bin\" mode=\"-rw"));
+            assert!(xml.contains(" size=\"2048\">"));
         }
-        return <&str as ColoredCompat>::colored_blue(name).bold();
     }
 
-    #[cfg(unix)]
-    {
-        let mode = metadata.permissions().mode();
-        // Check for setuid bit
-        let is_setuid = mode & MODE_SETUID != 0;
-        // Check for setgid bit
-        let is_setgid = mode & MODE_SETGID != 0;
-        // Check if file is executable
-        let is_executable = mode & MODE_EXECUTABLE != 0;
-        // Check if file is writable by others
-        let is_world_writable = mode & MODE_WORLD_WRITABLE != 0;
-        // Check for special file types using file_type()
-        let file_type = metadata.file_type();
-        use std::os::unix::fs::FileTypeExt;
-        // Character or block devices
-        if file_type.is_char_device() || file_type.is_block_device() {
-            return <&str as ColoredCompat>::colored_yellow(name).bold();
-        }
-        // Socket or FIFO (named pipe)
-        if file_type.is_socket() || file_type.is_fifo() {
-            return <&str as ColoredCompat>::colored_yellow(name);
-        }
-        // Setuid files (highest priority - security sensitive)
-        if is_setuid {
-            return <&str as ColoredCompat>::colored_white(name).on_red(); // White text on red background
-        }
-        // Setgid files (high priority - security sensitive)
-        if is_setgid {
-            return <&str as ColoredCompat>::colored_black(name).on_yellow(); // Black text on yellow background
-        }
-        // World-writable files (warning)
-        if is_world_writable {
-            return <&str as ColoredCompat>::colored_yellow(name);
-        }
-        // Executable files
-        if is_executable {
-            return <&str as ColoredCompat>::colored_green(name);
-        }
-    }
+    #[test]
+    fn test_du_totals_are_aggregated_bottom_up() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("a/b")).expect("Failed to create dir");
+        fs::write(test_dir.join("a/one"), vec![0u8; 1000]).expect("Failed to write file");
+        fs::write(test_dir.join("a/b/two"), vec![0u8; 3000]).expect("Failed to write file");
+        fs::write(test_dir.join("top"), vec![0u8; 500]).expect("Failed to write file");
 
-    // Check file extension for type-based coloring
-    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        let ext_lower = ext.to_lowercase();
-        // Archive files
-        if matches!(
-            ext_lower.as_str(),
-            "zip" | "tar" | "gz" | "bz2" | "xz" | "7z" | "rar" | "tgz" | "tbz2" | "txz"
-        ) {
-            return <&str as ColoredCompat>::colored_red(name);
-        }
-        // Image files
-        if matches!(
-            ext_lower.as_str(),
-            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "ico" | "webp" | "tiff" | "tif"
-        ) {
-            return <&str as ColoredCompat>::colored_magenta(name);
-        }
-        // Audio/video files
-        if matches!(
-            ext_lower.as_str(),
-            "mp3" | "mp4" | "avi" | "mkv" | "flac" | "wav" | "ogg" | "mov" | "wmv" | "webm" | "m4a"
-        ) {
-            return <&str as ColoredCompat>::colored_bright_magenta(name);
-        }
-    }
+        let mut tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let dir_size = |path: &Path| fs::symlink_metadata(path).unwrap().len();
+        let total = tree.compute_du(&WalkOptions::new(&test_dir), DuMode::Apparent);
+        let b = dir_size(&test_dir.join("a/b")) + 3000;
+        let a = dir_size(&test_dir.join("a")) + 1000 + b;
+        assert_eq!(tree.children[0].children[0].du_total, Some(b));
+        assert_eq!(tree.children[0].du_total, Some(a));
+        assert_eq!(total, dir_size(&test_dir) + a + 500);
+        assert_eq!(tree.children[1].du_total, None);
 
-    // Default: no special color
-    name.normal()
-}
+        // Directories below the depth limit are still counted
+        let shallow = WalkOptions {
+            max_depth: Some(1),
+            ..WalkOptions::new(&test_dir)
+        };
+        let mut tree = TreeNode::from_path(&test_dir, &shallow, 0).unwrap();
+        assert_eq!(tree.compute_du(&shallow, DuMode::Apparent), total);
+        assert_eq!(tree.children[0].du_total, Some(a));
 
-// Disambiguate colored methods
-#[allow(dead_code)]
-trait ColoredCompat {
-    fn colored_cyan(s: &str) -> ColoredString;
-    fn colored_green(s: &str) -> ColoredString;
-    fn colored_blue(s: &str) -> ColoredString;
-    fn colored_yellow(s: &str) -> ColoredString;
-    fn colored_white(s: &str) -> ColoredString;
-    fn colored_black(s: &str) -> ColoredString;
-    fn colored_red(s: &str) -> ColoredString;
-    fn colored_magenta(s: &str) -> ColoredString;
-    fn colored_bright_magenta(s: &str) -> ColoredString;
-}
+        // So are the entries left out by --filelimit
+        let limited = WalkOptions {
+            file_limit: Some(1),
+            ..WalkOptions::new(&test_dir)
+        };
+        let mut tree = TreeNode::from_path(&test_dir, &limited, 0).unwrap();
+        assert_eq!(tree.compute_du(&limited, DuMode::Apparent), total);
+        assert_eq!(tree.children[0].du_total, Some(a));
 
-impl ColoredCompat for &str {
-    fn colored_cyan(s: &str) -> ColoredString {
-        colored::Colorize::cyan(s)
-    }
-    fn colored_green(s: &str) -> ColoredString {
-        colored::Colorize::green(s)
-    }
-    fn colored_blue(s: &str) -> ColoredString {
-        colored::Colorize::blue(s)
-    }
-    fn colored_yellow(s: &str) -> ColoredString {
-        colored::Colorize::yellow(s)
-    }
-    fn colored_white(s: &str) -> ColoredString {
-        colored::Colorize::white(s)
-    }
-    fn colored_black(s: &str) -> ColoredString {
-        colored::Colorize::black(s)
-    }
-    fn colored_red(s: &str) -> ColoredString {
-        colored::Colorize::red(s)
-    }
-    fn colored_magenta(s: &str) -> ColoredString {
-        colored::Colorize::magenta(s)
-    }
-    fn colored_bright_magenta(s: &str) -> ColoredString {
-        colored::Colorize::bright_magenta(s)
+        let cli = Cli::parse_from(["rutree2", "--du"]);
+        let columns = Columns::from_cli(&cli);
+        assert_eq!(columns.du, Some(DuMode::Apparent));
+        assert_eq!(columns.size, Some(SizeFormat::Bytes));
+        let cli = Cli::parse_from(["rutree2", "-h", "--du=allocated"]);
+        assert_eq!(Columns::from_cli(&cli).du, Some(DuMode::Allocated));
+        assert!(Cli::try_parse_from(["rutree2", "--du=blocks"]).is_err());
     }
-}
 
-// Helper to get mutable reference to the selected node in the real tree using path indices
-fn get_mut_node(tree: &mut TreeNode, sel: usize) -> Option<&mut TreeNode> {
-    let path = tree.get_path_to_flat_index(sel)?;
-    let mut node = tree;
-    for idx in path {
-        node = node.children.get_mut(idx)?;
+    #[test]
+    fn test_mode_string() {
+        assert_eq!(mode_string(EntryKind::Directory, 0o2755), "drwxr-sr-x");
+        assert_eq!(mode_string(EntryKind::File, 0o644), "-rw-r--r--");
+        assert_eq!(mode_string(EntryKind::File, 0o4644), "-rwSr--r--");
+        assert_eq!(mode_string(EntryKind::Directory, 0o1777), "drwxrwxrwt");
+        assert_eq!(mode_string(EntryKind::Symlink, 0o777), "lrwxrwxrwx");
     }
-    Some(node)
-}
 
-// Get the path of child indices from root to the selected node in the flat list
-impl TreeNode {
-    fn get_path_to_flat_index(&self, sel: usize) -> Option<Vec<usize>> {
-        // Build flat list with path information
-        let mut flat_with_paths: Vec<Vec<usize>> = vec![];
-        self.flatten_with_paths(&mut flat_with_paths, vec![]);
-        flat_with_paths.get(sel).cloned()
-    }
+    #[test]
+    fn test_long_columns() {
+        let cli = Cli::parse_from(["rutree2", "-l"]);
+        assert_eq!(Columns::from_cli(&cli).long, DEFAULT_LONG_COLUMNS);
+        let cli = Cli::parse_from(["rutree2", "--columns", "links,mode"]);
+        assert_eq!(
+            Columns::from_cli(&cli).long,
+            [LongColumn::Links, LongColumn::Mode]
+        );
+        assert!(Cli::try_parse_from(["rutree2", "--columns", "mode,size"]).is_err());
 
-    fn flatten_with_paths(&self, out: &mut Vec<Vec<usize>>, current_path: Vec<usize>) {
-        out.push(current_path.clone());
-        if self.is_dir && self.expanded {
-            for (i, child) in self.children.iter().enumerate() {
-                let mut child_path = current_path.clone();
-                child_path.push(i);
-                child.flatten_with_paths(out, child_path);
-            }
+        #[cfg(unix)]
+        {
+            let (test_dir, _temp) = create_test_dir();
+            let path = test_dir.join("run.sh");
+            File::create(&path)
+                .expect("Failed to create file")
+                .set_modified(UNIX_EPOCH + Duration::from_secs(1_790_000_000))
+                .expect("Failed to set mtime");
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o750))
+                .expect("Failed to set permissions");
+            let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+            let columns = Columns {
+                long: vec![LongColumn::Mode, LongColumn::Mtime, LongColumn::Links],
+                ..Columns::default()
+            };
+            assert_eq!(
+                columns.prefix(&tree.children[0]),
+                "[-rwxr-x--- 2026-09-21 14:13   1]  "
+            );
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
 
     #[test]
-    fn test_cli_interactive_flag() {
-        use clap::Parser;
-        let cli = Cli::parse_from(["rutree2", "-i"]);
-        assert!(cli.interactive);
-    }
+    fn test_tree_stats_footer() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("src/empty")).expect("Failed to create dir");
+        fs::write(test_dir.join("src/main.rs"), vec![b'x'; 1536]).expect("Failed to write file");
+        fs::write(test_dir.join("README"), b"hi").expect("Failed to write file");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("README", test_dir.join("link"))
+            .expect("Failed to create symlink");
 
-    use super::*;
-    use std::fs::{self, File};
-    use std::path::PathBuf;
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let stats = TreeStats::of(&tree);
+        assert_eq!((stats.directories, stats.files), (2, 2));
+        #[cfg(unix)]
+        {
+            assert_eq!(stats.symlinks, 1);
+            assert!(
+                stats
+                    .to_string()
+                    .starts_with("2 directories, 2 files, 1 symlink, ")
+            );
+        }
 
-    /// Helper function to create a temporary test directory
-    fn create_test_dir() -> (PathBuf, tempfile::TempDir) {
-        let temp_dir = tempfile::TempDir::new().expect("Failed to create temp dir");
-        let path = temp_dir.path().to_path_buf();
-        (path, temp_dir)
+        let listing = ["a/b".to_string()];
+        let tree = TreeNode::from_listing(".", &listing, &WalkOptions::new(Path::new(".")));
+        assert_eq!(
+            TreeStats::of(&tree).to_string(),
+            "1 directory, 1 file, 0 symlinks"
+        );
+        assert_eq!(format_total_size(1), "1 byte");
+        assert_eq!(format_total_size(1538), "1.5 KiB");
+        assert_eq!(format_total_size(1_288_490_189), "1.2 GiB");
     }
 
     #[test]
-    fn test_validate_color_valid_values() {
-        assert!(validate_color("auto").is_ok());
-        assert!(validate_color("always").is_ok());
-        assert!(validate_color("never").is_ok());
-    }
+    fn test_extension_stats_report() {
+        let (test_dir, _temp) = create_test_dir();
+        fs::create_dir_all(test_dir.join("assets")).expect("Failed to create dir");
+        fs::write(test_dir.join("assets/a.PNG"), vec![0u8; 3000]).expect("Failed to write file");
+        fs::write(test_dir.join("assets/b.png"), vec![0u8; 1000]).expect("Failed to write file");
+        fs::write(test_dir.join("assets/music.ogg"), vec![0u8; 500]).expect("Failed to write file");
+        fs::write(test_dir.join("Makefile"), b"all:").expect("Failed to write file");
 
-    #[test]
-    fn test_validate_color_invalid_value() {
-        let result = validate_color("invalid");
-        assert!(result.is_err());
+        let tree = TreeNode::from_path(&test_dir, &WalkOptions::new(&test_dir), 0).unwrap();
+        let stats = ExtensionStats::of(&tree);
+        assert_eq!(stats.total, (4, 4504));
+        assert_eq!(
+            stats.extension_rows(),
+            [("png", 2, 4000), ("ogg", 1, 500), ("", 1, 4)]
+        );
+        assert_eq!(
+            render_stats(&stats, "csv"),
+            "group,key,files,bytes\n\
+             extension,png,2,4000\nextension,ogg,1,500\nextension,,1,4\n\
+             category,image,2,4000\ncategory,media,1,500\ncategory,other,1,4\n\
+             total,,4,4504\n"
+        );
+        let text = render_stats(&stats, "tree");
+        assert!(text.contains("(none)"));
+        assert!(text.ends_with("Total                   4      4.4 KiB\n"));
         assert!(
-            result
-                .unwrap_err()
-                .contains("must be one of: auto, always, never")
+            render_stats(&stats, "json")
+                .contains("{\"category\": \"image\", \"files\": 2, \"bytes\": 4000}")
         );
+        assert!(Cli::try_parse_from(["rutree2", "--stats", "-i"]).is_err());
+        let validate = |output| {
+            let cli = Cli::try_parse_from(["rutree2", "--stats", "-o", output]).unwrap();
+            cli.validate().is_ok()
+        };
+        assert!(validate("csv"));
+        assert!(!validate("xml"));
+        assert!(!validate("dot"));
     }
 
     #[test]
     fn test_display_tree_empty_directory() {
         let (test_dir, _temp) = create_test_dir();
-        let result = display_tree(&test_dir, false, None, "", 0);
+        let result = display_tree(
+            &test_dir,
+            &WalkOptions::new(&test_dir),
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         assert!(result.is_ok());
     }
 
@@ -754,7 +5096,12 @@ mod tests {
         File::create(test_dir.join("file2.rs")).expect("Failed to create file");
         fs::create_dir(test_dir.join("subdir")).expect("Failed to create directory");
 
-        let result = display_tree(&test_dir, false, None, "", 0);
+        let result = display_tree(
+            &test_dir,
+            &WalkOptions::new(&test_dir),
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         assert!(result.is_ok());
     }
 
@@ -767,11 +5114,24 @@ mod tests {
         File::create(test_dir.join("visible.txt")).expect("Failed to create file");
 
         // Should succeed with show_hidden=false
-        let result = display_tree(&test_dir, false, None, "", 0);
+        let result = display_tree(
+            &test_dir,
+            &WalkOptions::new(&test_dir),
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         assert!(result.is_ok());
 
         // Should succeed with show_hidden=true
-        let result = display_tree(&test_dir, true, None, "", 0);
+        let result = display_tree(
+            &test_dir,
+            &WalkOptions {
+                show_hidden: true,
+                ..WalkOptions::new(&test_dir)
+            },
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         assert!(result.is_ok());
     }
 
@@ -789,17 +5149,38 @@ mod tests {
         fs::create_dir(&subdir3).expect("Failed to create dir");
 
         // Test with depth limit
-        let result = display_tree(&test_dir, false, Some(2), "", 0);
+        let result = display_tree(
+            &test_dir,
+            &WalkOptions {
+                max_depth: Some(2),
+                ..WalkOptions::new(&test_dir)
+            },
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         assert!(result.is_ok());
 
-        let result = display_tree(&test_dir, false, Some(0), "", 0);
+        let result = display_tree(
+            &test_dir,
+            &WalkOptions {
+                max_depth: Some(0),
+                ..WalkOptions::new(&test_dir)
+            },
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_display_tree_nonexistent_directory() {
         let nonexistent = PathBuf::from("/path/that/does/not/exist/directory");
-        let result = display_tree(&nonexistent, false, None, "", 0);
+        let result = display_tree(
+            &nonexistent,
+            &WalkOptions::new(&nonexistent),
+            &Charset::utf8(),
+            &Columns::default(),
+        );
         // For non-directory paths, display_tree returns Ok since it just checks is_dir()
         // which returns false for nonexistent paths without erroring
         assert!(result.is_ok());